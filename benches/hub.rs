@@ -0,0 +1,147 @@
+//! Benchmarks of the hub state with thousands of clients
+//!
+//! Run them with `cargo bench --features bench --bench hub`, and compare
+//! with a saved baseline by `--save-baseline` and `--baseline` of criterion.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use server::bench::{
+    Client, CompressStats, DropStats, HubState, Mailbox, Message, RoomAction, SlowClientPolicy,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
+
+const NUM_CLIENTS: i64 = 5000;
+const NUM_ROOMS: i64 = 50;
+const NUM_EVENTS: usize = 50;
+const CHAN_CAPACITY: usize = 100;
+
+/// Connect all clients at the same time
+async fn connect(hub: &Arc<HubState>, stats: &Arc<DropStats>) -> Vec<Client> {
+    let mut tasks = Vec::new();
+    for user_id in 1..=NUM_CLIENTS {
+        let hub = hub.clone();
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats.clone());
+        let client = Client::new(user_id, NUM_ROOMS + user_id, mailbox);
+        tasks.push(tokio::spawn(async move {
+            let rooms = vec![user_id % NUM_ROOMS];
+            hub.register_client(&client, rooms).await.unwrap();
+            client
+        }));
+    }
+
+    let mut clients = Vec::new();
+    for task in tasks {
+        clients.push(task.await.unwrap());
+    }
+    clients
+}
+
+/// Broadcast to every room while the clients are reading
+async fn broadcast(hub: &Arc<HubState>, clients: &[Client]) {
+    let mut tasks = Vec::new();
+    for client in clients {
+        let client = client.clone();
+        tasks.push(tokio::spawn(async move {
+            for _ in 0..NUM_EVENTS {
+                client.recv().await.unwrap();
+            }
+        }));
+    }
+
+    for _ in 0..NUM_EVENTS {
+        for room_id in 0..NUM_ROOMS {
+            let tx = hub.room_chan(room_id).await.unwrap();
+            let msg = Message::from_text("{}".to_owned()).unwrap();
+            tx.send(RoomAction::Send(msg)).await.unwrap();
+        }
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+/// Disconnect all clients at the same time
+async fn disconnect(hub: &Arc<HubState>, clients: Vec<Client>) {
+    let mut tasks = Vec::new();
+    for client in clients {
+        let hub = hub.clone();
+        tasks.push(tokio::spawn(async move {
+            hub.unregister_client(&client).await.unwrap();
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+fn bench_hub(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("hub");
+    group.sample_size(10);
+
+    group.bench_function("connect", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let hub = Arc::new(HubState::default());
+                let stats = Arc::new(DropStats::default());
+
+                let start = Instant::now();
+                let clients = connect(&hub, &stats).await;
+                total += start.elapsed();
+
+                disconnect(&hub, clients).await;
+            }
+            total
+        })
+    });
+
+    group.bench_function("broadcast", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let hub = Arc::new(HubState::default());
+                let stats = Arc::new(DropStats::default());
+                let clients = connect(&hub, &stats).await;
+
+                let start = Instant::now();
+                broadcast(&hub, &clients).await;
+                total += start.elapsed();
+
+                // no event is dropped if the clients keep up
+                assert_eq!(stats.load(), (0, 0));
+                disconnect(&hub, clients).await;
+            }
+            total
+        })
+    });
+
+    group.bench_function("disconnect", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let hub = Arc::new(HubState::default());
+                let stats = Arc::new(DropStats::default());
+                let clients = connect(&hub, &stats).await;
+
+                let start = Instant::now();
+                disconnect(&hub, clients).await;
+                total += start.elapsed();
+
+                // the rooms are kept for the users to resume
+                let rsp = hub.status(&stats, &CompressStats::default()).await.unwrap();
+                assert_eq!(rsp.num_clients, 0);
+                assert_eq!(rsp.num_idle_rooms, NUM_ROOMS as usize);
+            }
+            total
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_hub);
+criterion_main!(benches);
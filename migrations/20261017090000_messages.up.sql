@@ -0,0 +1,16 @@
+CREATE TABLE "messages" (
+  "id" bigserial PRIMARY KEY,
+  "room_id" bigint NOT NULL,
+  "sender_id" bigint NOT NULL,
+  "content" varchar NOT NULL,
+  "file_url" varchar NOT NULL,
+  "kind" varchar NOT NULL,
+  "divide" boolean NOT NULL DEFAULT false,
+  "send_at" timestamptz NOT NULL DEFAULT (now())
+);
+
+CREATE INDEX ON "messages" ("room_id", "id");
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("sender_id") REFERENCES "users" ("id") ON DELETE CASCADE;
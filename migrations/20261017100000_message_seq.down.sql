@@ -0,0 +1,3 @@
+ALTER TABLE "messages" DROP COLUMN IF EXISTS "seq";
+
+ALTER TABLE "rooms" DROP COLUMN IF EXISTS "last_seq";
\ No newline at end of file
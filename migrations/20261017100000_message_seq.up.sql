@@ -0,0 +1,24 @@
+ALTER TABLE "rooms" ADD COLUMN "last_seq" bigint NOT NULL DEFAULT 0;
+
+ALTER TABLE "messages" ADD COLUMN "seq" bigint NOT NULL DEFAULT 0;
+
+UPDATE "messages" AS m
+SET "seq" = s.seq
+FROM (
+  SELECT id, row_number() OVER (PARTITION BY room_id ORDER BY id) AS seq
+  FROM "messages"
+) AS s
+WHERE m.id = s.id;
+
+UPDATE "rooms" AS r
+SET "last_seq" = s.seq
+FROM (
+  SELECT room_id, max(seq) AS seq
+  FROM "messages"
+  GROUP BY room_id
+) AS s
+WHERE r.id = s.room_id;
+
+ALTER TABLE "messages" ALTER COLUMN "seq" DROP DEFAULT;
+
+CREATE UNIQUE INDEX ON "messages" ("room_id", "seq");
@@ -0,0 +1 @@
+ALTER TABLE "members" DROP COLUMN IF EXISTS "last_read_seq";
\ No newline at end of file
@@ -0,0 +1,6 @@
+ALTER TABLE "members" ADD COLUMN "last_read_seq" bigint NOT NULL DEFAULT 0;
+
+UPDATE "members" AS y
+SET "last_read_seq" = r.last_seq
+FROM "rooms" AS r
+WHERE r.id = y.room_id;
@@ -0,0 +1 @@
+ALTER TABLE "users" ADD COLUMN "last_seen" timestamptz;
\ No newline at end of file
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS message_edits;
+
+ALTER TABLE "messages" DROP COLUMN IF EXISTS "deleted";
+
+ALTER TABLE "messages" DROP COLUMN IF EXISTS "edit_at";
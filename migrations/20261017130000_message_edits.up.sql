@@ -0,0 +1,14 @@
+ALTER TABLE "messages" ADD COLUMN "edit_at" timestamptz;
+
+ALTER TABLE "messages" ADD COLUMN "deleted" boolean NOT NULL DEFAULT false;
+
+CREATE TABLE "message_edits" (
+  "id" bigserial PRIMARY KEY,
+  "message_id" bigint NOT NULL,
+  "content" varchar NOT NULL,
+  "edit_at" timestamptz NOT NULL DEFAULT (now())
+);
+
+CREATE INDEX ON "message_edits" ("message_id");
+
+ALTER TABLE "message_edits" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
@@ -0,0 +1,5 @@
+ALTER TABLE "messages" ADD COLUMN "parent_id" bigint;
+
+CREATE INDEX ON "messages" ("parent_id");
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("parent_id") REFERENCES "messages" ("id") ON DELETE SET NULL;
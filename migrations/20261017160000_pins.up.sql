@@ -0,0 +1,13 @@
+CREATE TABLE "pins" (
+  "room_id" bigint NOT NULL,
+  "message_id" bigint NOT NULL,
+  "pinner_id" bigint NOT NULL,
+  "pin_at" timestamptz NOT NULL DEFAULT (now()),
+  PRIMARY KEY ("room_id", "message_id")
+);
+
+ALTER TABLE "pins" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "pins" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "pins" ADD FOREIGN KEY ("pinner_id") REFERENCES "users" ("id") ON DELETE CASCADE;
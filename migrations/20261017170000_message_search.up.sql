@@ -0,0 +1 @@
+CREATE INDEX "messages_content_search_idx" ON "messages" USING GIN (to_tsvector('simple', "content"));
@@ -0,0 +1,3 @@
+ALTER TABLE "messages" DROP COLUMN IF EXISTS "forward_room_id";
+
+ALTER TABLE "messages" DROP COLUMN IF EXISTS "forward_sender_id";
@@ -0,0 +1,5 @@
+ALTER TABLE "messages" ADD COLUMN "forward_sender_id" bigint;
+
+ALTER TABLE "messages" ADD COLUMN "forward_room_id" bigint;
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("forward_sender_id") REFERENCES "users" ("id") ON DELETE SET NULL;
@@ -0,0 +1,12 @@
+CREATE TABLE "saved_messages" (
+  "id" bigserial PRIMARY KEY,
+  "user_id" bigint NOT NULL,
+  "message_id" bigint NOT NULL,
+  "save_at" timestamptz NOT NULL DEFAULT (now())
+);
+
+CREATE UNIQUE INDEX ON "saved_messages" ("user_id", "message_id");
+
+ALTER TABLE "saved_messages" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "saved_messages" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS "messages_sender_client_idx";
+
+ALTER TABLE "messages" DROP COLUMN IF EXISTS "client_id";
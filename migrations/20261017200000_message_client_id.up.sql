@@ -0,0 +1,3 @@
+ALTER TABLE "messages" ADD COLUMN "client_id" varchar;
+
+CREATE UNIQUE INDEX "messages_sender_client_idx" ON "messages" ("sender_id", "client_id");
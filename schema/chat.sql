@@ -11,6 +11,7 @@ CREATE TABLE "users" (
   "role" varchar NOT NULL,
   "room_id" bigint NOT NULL,
   "deleted" boolean NOT NULL DEFAULT false,
+  "last_seen" timestamptz,
   "create_at" timestamptz NOT NULL DEFAULT (now())
 );
 
@@ -28,6 +29,7 @@ CREATE TABLE "rooms" (
   "name" varchar NOT NULL,
   "cover" varchar NOT NULL,
   "category" varchar NOT NULL,
+  "last_seq" bigint NOT NULL DEFAULT 0,
   "create_at" timestamptz NOT NULL DEFAULT (now())
 );
 
@@ -35,10 +37,73 @@ CREATE TABLE "members" (
   "member_id" bigint,
   "room_id" bigint,
   "rank" varchar NOT NULL,
+  "last_read_seq" bigint NOT NULL DEFAULT 0,
   "join_at" timestamptz NOT NULL DEFAULT (now()),
   PRIMARY KEY ("room_id", "member_id")
 );
 
+CREATE TABLE "messages" (
+  "id" bigserial PRIMARY KEY,
+  "room_id" bigint NOT NULL,
+  "seq" bigint NOT NULL,
+  "sender_id" bigint NOT NULL,
+  "parent_id" bigint,
+  "forward_sender_id" bigint,
+  "forward_room_id" bigint,
+  "content" varchar NOT NULL,
+  "file_url" varchar NOT NULL,
+  "kind" varchar NOT NULL,
+  "divide" boolean NOT NULL DEFAULT false,
+  "deleted" boolean NOT NULL DEFAULT false,
+  "edit_at" timestamptz,
+  "send_at" timestamptz NOT NULL DEFAULT (now()),
+  "client_id" varchar
+);
+
+CREATE TABLE "message_edits" (
+  "id" bigserial PRIMARY KEY,
+  "message_id" bigint NOT NULL,
+  "content" varchar NOT NULL,
+  "edit_at" timestamptz NOT NULL DEFAULT (now())
+);
+
+CREATE TABLE "reactions" (
+  "message_id" bigint NOT NULL,
+  "user_id" bigint NOT NULL,
+  "emoji" varchar NOT NULL,
+  "create_at" timestamptz NOT NULL DEFAULT (now()),
+  PRIMARY KEY ("message_id", "user_id", "emoji")
+);
+
+CREATE TABLE "pins" (
+  "room_id" bigint NOT NULL,
+  "message_id" bigint NOT NULL,
+  "pinner_id" bigint NOT NULL,
+  "pin_at" timestamptz NOT NULL DEFAULT (now()),
+  PRIMARY KEY ("room_id", "message_id")
+);
+
+CREATE TABLE "saved_messages" (
+  "id" bigserial PRIMARY KEY,
+  "user_id" bigint NOT NULL,
+  "message_id" bigint NOT NULL,
+  "save_at" timestamptz NOT NULL DEFAULT (now())
+);
+
+CREATE INDEX ON "messages" ("room_id", "id");
+
+CREATE UNIQUE INDEX ON "messages" ("room_id", "seq");
+
+CREATE INDEX ON "messages" ("parent_id");
+
+CREATE INDEX "messages_content_search_idx" ON "messages" USING GIN (to_tsvector('simple', "content"));
+
+CREATE UNIQUE INDEX "messages_sender_client_idx" ON "messages" ("sender_id", "client_id");
+
+CREATE INDEX ON "message_edits" ("message_id");
+
+CREATE UNIQUE INDEX ON "saved_messages" ("user_id", "message_id");
+
 ALTER TABLE "users" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id");
 
 ALTER TABLE "friends" ADD FOREIGN KEY ("requester_id") REFERENCES "users" ("id");
@@ -50,3 +115,27 @@ ALTER TABLE "friends" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id");
 ALTER TABLE "members" ADD FOREIGN KEY ("member_id") REFERENCES "users" ("id");
 
 ALTER TABLE "members" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id");
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("sender_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("parent_id") REFERENCES "messages" ("id") ON DELETE SET NULL;
+
+ALTER TABLE "messages" ADD FOREIGN KEY ("forward_sender_id") REFERENCES "users" ("id") ON DELETE SET NULL;
+
+ALTER TABLE "message_edits" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "reactions" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "reactions" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "pins" ADD FOREIGN KEY ("room_id") REFERENCES "rooms" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "pins" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "pins" ADD FOREIGN KEY ("pinner_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "saved_messages" ADD FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE;
+
+ALTER TABLE "saved_messages" ADD FOREIGN KEY ("message_id") REFERENCES "messages" ("id") ON DELETE CASCADE;
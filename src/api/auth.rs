@@ -49,7 +49,7 @@ async fn login(
 
     // create access and refresh tokens pair
     let JwtTokenPair(access_token, refresh_token, session_id, expire_at) =
-        state.jwt.create_pair((&user).into())?;
+        state.jwt.create_pair(&user)?;
 
     // create session to save refresh token in redis
     state
@@ -143,12 +143,12 @@ async fn extract_claims(state: &Arc<AppState>, refresh_token: String) -> Result<
     }
 
     // check whether refresh_token exists in session
-    let refresh_token = state
+    let session_token = state
         .db
         .get_session(claims.id)
         .await
         .map_err(|_| Error::Unauthorized)?;
-    if refresh_token != refresh_token {
+    if session_token != refresh_token {
         return Err(Error::Unauthorized);
     }
 
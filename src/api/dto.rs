@@ -2,7 +2,10 @@
 
 use crate::{
     core::validator as VAL,
-    store::{FriendInfo, MemberInfo, MessageInfo, RoomInfo, UserInfo},
+    store::{
+        EditInfo, FriendInfo, MemberInfo, MessageInfo, PinInfo, RoomInfo, SavedMessageInfo,
+        SearchResultInfo, UserInfo,
+    },
 };
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
@@ -141,10 +144,31 @@ pub struct FindUserResponse {
 #[derive(Deserialize)]
 pub struct InitializeRequest {}
 
+/// The cursor is the latest event of the user, and events after it can be
+/// replayed with the resume token if the client reconnects in time.
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct InitializeResponse {
     pub rooms: Vec<RoomInfo>,
     pub friends: Vec<FriendInfo>,
+    pub resume_token: String,
+    pub cursor: u64,
+}
+
+/// Used to get the events missed since the cursor after reconnecting
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeRequest {
+    #[validate(length(min = 1, message = "Invalid resume token"))]
+    pub resume_token: String,
+    pub cursor: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeResponse {
+    pub resume_token: String,
+    pub cursor: u64,
 }
 
 #[derive(Deserialize, Validate)]
@@ -157,11 +181,298 @@ pub struct NewMessageRequest {
     pub file_url: String,
     #[validate(custom = "VAL::validate_message_kind")]
     pub kind: String,
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub parent_id: Option<i64>,
+    #[validate(length(min = 1, max = 64, message = "Must be between 1 and 64 characters"))]
+    pub client_id: Option<String>,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct NewMessageResponse {
     pub message: MessageInfo,
+    pub client_id: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMessageRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+    #[validate(length(min = 1, max = 500, message = "Must be between 1 and 500 characters"))]
+    pub content: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMessageResponse {
+    pub room_id: i64,
+    pub message_id: i64,
+    pub content: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub edit_at: OffsetDateTime,
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessageRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessageResponse {
+    pub room_id: i64,
+    pub message_id: i64,
+}
+
+/// Used to copy messages into other rooms of the user
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ForwardMessagesRequest {
+    #[validate(
+        length(min = 1, max = 20, message = "Must have between 1 and 20 messages"),
+        custom = "VAL::validate_id_vec"
+    )]
+    pub messages_id: Vec<i64>,
+    #[validate(
+        length(min = 1, max = 10, message = "Must have between 1 and 10 rooms"),
+        custom = "VAL::validate_id_vec"
+    )]
+    pub rooms_id: Vec<i64>,
+}
+
+/// Used to add or remove a reaction on a message
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+    #[validate(custom = "VAL::validate_emoji")]
+    pub emoji: String,
+}
+
+/// The count is the number of users reacting with the emoji after the change
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionResponse {
+    pub room_id: i64,
+    pub message_id: i64,
+    pub user_id: i64,
+    pub emoji: String,
+    pub count: i64,
+}
+
+/// Used to pin or unpin a message in its room
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PinMessageRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinMessageResponse {
+    pub room_id: i64,
+    pub pin: PinInfo,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnpinMessageResponse {
+    pub room_id: i64,
+    pub message_id: i64,
+}
+
+/// Used to scroll through the message history of a room
+///
+/// The cursors are sequence numbers of messages in the room, and the
+/// latest page is returned if neither `before` nor `after` is given.
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ListMessagesRequest {
+    #[validate(range(min = 1, message = "Invalid room ID"))]
+    pub room_id: i64,
+    #[validate(range(min = 1, message = "Invalid cursor"))]
+    pub before: Option<i64>,
+    #[validate(range(min = 1, message = "Invalid cursor"))]
+    pub after: Option<i64>,
+    #[validate(range(min = 5, max = 50, message = "Must be between 5 and 50"))]
+    pub page_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListMessagesResponse {
+    pub room_id: i64,
+    pub messages: Vec<MessageInfo>,
+    pub has_more: bool,
+}
+
+/// Used to move the read marker of the user in a room
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkReadRequest {
+    #[validate(range(min = 1, message = "Invalid room ID"))]
+    pub room_id: i64,
+    #[validate(range(min = 1, message = "Invalid sequence"))]
+    pub seq: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkReadResponse {
+    pub room_id: i64,
+    pub last_read_seq: i64,
+    pub unreads: i64,
+}
+
+/// Used to save or unsave a message for the user
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveMessageRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveMessageResponse {
+    pub id: i64,
+    pub message_id: i64,
+    pub room_id: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub save_at: OffsetDateTime,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsaveMessageResponse {
+    pub message_id: i64,
+}
+
+/// Used to list the saved messages of the user
+///
+/// Returns the latest page if `before` is not given.
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSavedRequest {
+    #[validate(range(min = 1, message = "Invalid cursor"))]
+    pub before: Option<i64>,
+    #[validate(range(min = 5, max = 50, message = "Must be between 5 and 50"))]
+    pub page_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSavedResponse {
+    pub saved: Vec<SavedMessageInfo>,
+    pub has_more: bool,
+}
+
+/// Used to search messages in all rooms of the user
+///
+/// Results are returned with the newest first, and `before` is the id
+/// of the last result on the previous page.
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMessagesRequest {
+    #[validate(length(min = 1, max = 100, message = "Must be between 1 and 100 characters"))]
+    pub keyword: String,
+    #[validate(range(min = 1, message = "Invalid room ID"))]
+    pub room_id: Option<i64>,
+    #[validate(range(min = 1, message = "Invalid user ID"))]
+    pub sender_id: Option<i64>,
+    #[validate(custom = "VAL::validate_message_kind")]
+    pub kind: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub since: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub until: Option<OffsetDateTime>,
+    #[validate(range(min = 1, message = "Invalid cursor"))]
+    pub before: Option<i64>,
+    #[validate(range(min = 5, max = 50, message = "Must be between 5 and 50"))]
+    pub page_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMessagesResponse {
+    pub results: Vec<SearchResultInfo>,
+    pub has_more: bool,
+}
+
+/// Used to get the replies in the thread of a message
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ListRepliesRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListRepliesResponse {
+    pub message_id: i64,
+    pub room_id: i64,
+    pub replies: Vec<MessageInfo>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadReceiptResponse {
+    pub room_id: i64,
+    pub member_id: i64,
+    pub last_read_seq: i64,
+}
+
+/// Used to get the members who have read a message
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ListReadersRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListReadersResponse {
+    pub message_id: i64,
+    pub room_id: i64,
+    pub readers: Vec<MemberInfo>,
+}
+
+/// Used to get the edit history of a message
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ListEditsRequest {
+    #[validate(range(min = 1, message = "Invalid message ID"))]
+    pub message_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListEditsResponse {
+    pub message_id: i64,
+    pub room_id: i64,
+    pub edits: Vec<EditInfo>,
+}
+
+/// Used to start or stop typing in a room
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingRequest {
+    #[validate(range(min = 1, message = "Invalid room ID"))]
+    pub room_id: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingResponse {
+    pub room_id: i64,
+    pub user_id: i64,
 }
 
 #[derive(Serialize)]
@@ -178,6 +489,11 @@ pub struct HubStatusResponse {
     pub num_users: usize,
     pub num_clients: usize,
     pub num_rooms: usize,
+    pub num_live_rooms: usize,
+    pub num_idle_rooms: usize,
+    pub num_dropped_events: u64,
+    pub num_slow_clients: u64,
+    pub compression_ratio: f64,
 }
 
 // ============================== // Room // ============================== //
@@ -335,3 +651,32 @@ pub struct DeleteFriendResponse {
     pub friend_id: i64,
     pub room_id: i64,
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceResponse {
+    pub user_id: i64,
+    pub online: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen: OffsetDateTime,
+}
+
+// ============================== // Event // ============================== //
+
+/// Sent when the event with the request id is processed successfully
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AckResponse {
+    pub request_id: String,
+}
+
+/// Sent when the event with the request id fails
+///
+/// The code is the HTTP status code that the error maps to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    pub request_id: String,
+    pub code: u16,
+    pub message: String,
+}
@@ -1,19 +1,135 @@
 //! Event objects defined for WebSocket
 
-use super::{friend, member, message, room, ChangeCoverResponse};
+use super::{friend, member, message, room, typing, ChangeCoverResponse};
 use super::{
-    AcceptFriendRequest, AcceptFriendResponse, AddFriendRequest, AddFriendResponse,
+    AcceptFriendRequest, AcceptFriendResponse, AckResponse, AddFriendRequest, AddFriendResponse,
     AddMembersRequest, AddMembersResponse, AppState, DeleteFriendRequest, DeleteFriendResponse,
-    DeleteMembersRequest, DeleteMembersResponse, DeleteRoomRequest, DeleteRoomResponse,
-    InitializeRequest, InitializeResponse, LeaveRoomRequest, NewMessageRequest, NewMessageResponse,
-    NewRoomRequest, NewRoomResponse, RefuseFriendRequest, RefuseFriendResponse, UpdateRoomResponse,
+    DeleteMembersRequest, DeleteMembersResponse, DeleteMessageRequest, DeleteMessageResponse,
+    DeleteRoomRequest, DeleteRoomResponse, EditMessageRequest, EditMessageResponse, ErrorResponse,
+    ForwardMessagesRequest, InitializeRequest, InitializeResponse, LeaveRoomRequest,
+    ListEditsRequest, ListEditsResponse, ListMessagesRequest, ListMessagesResponse,
+    ListReadersRequest, ListReadersResponse, ListRepliesRequest, ListRepliesResponse,
+    MarkReadRequest, MarkReadResponse, NewMessageRequest, NewMessageResponse, NewRoomRequest,
+    NewRoomResponse, PinMessageRequest, PinMessageResponse, PresenceResponse, ReactionRequest,
+    ReactionResponse, ReadReceiptResponse, RefuseFriendRequest, RefuseFriendResponse,
+    ResumeRequest, ResumeResponse, SaveMessageRequest, SaveMessageResponse, TypingRequest,
+    TypingResponse, UnpinMessageResponse, UnsaveMessageResponse, UpdateRoomResponse,
     UpdateRoomResquest,
 };
-use crate::{conn::Client, core::Error};
-use axum::extract::ws::Message;
+use crate::{
+    conn::{Client, Message},
+    core::{constant::WS_MSGPACK_PROTOCOL_KEY, Error},
+};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+// ============================== // ClientRequest // ============================== //
+
+/// A client event with an optional request id
+///
+/// If the request id is given, the server replies with an ack or an error
+/// carrying the same id after the event is processed.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientRequest {
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub event: ClientEvent,
+}
+
+/// Used to find the request id of a malformed event
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestId {
+    request_id: Option<String>,
+}
+
+impl ClientRequest {
+    /// Parse the JSON text message from the client and process the event
+    pub async fn process_text(
+        text: &str,
+        state: &Arc<AppState>,
+        client: &Client,
+    ) -> Result<(), Error> {
+        let req = serde_json::from_str::<ClientRequest>(text).map_err(|_| {
+            serde_json::from_str::<RequestId>(text)
+                .ok()
+                .and_then(|x| x.request_id)
+        });
+        Self::process(req, state, client).await
+    }
+
+    /// Parse the MessagePack binary message from the client and process the event
+    pub async fn process_binary(
+        data: &[u8],
+        state: &Arc<AppState>,
+        client: &Client,
+    ) -> Result<(), Error> {
+        let req = rmp_serde::from_slice::<ClientRequest>(data).map_err(|_| {
+            rmp_serde::from_slice::<RequestId>(data)
+                .ok()
+                .and_then(|x| x.request_id)
+        });
+        Self::process(req, state, client).await
+    }
+
+    /// Process the parsed event, or the request id of a malformed one
+    async fn process(
+        req: Result<ClientRequest, Option<String>>,
+        state: &Arc<AppState>,
+        client: &Client,
+    ) -> Result<(), Error> {
+        match req {
+            Ok(req) => {
+                // events are refused while the server is restarting
+                let result = if state.hub.is_closed() {
+                    Err(Error::ShuttingDown)
+                } else {
+                    req.event.process(state, client).await
+                };
+                reply(client, req.request_id, result).await
+            }
+            Err(request_id) => {
+                // only reply to the malformed event that can be correlated
+                if request_id.is_some() {
+                    reply(client, request_id, Err(Error::BadRequest)).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Send the result of the event to the client
+///
+/// Errors of events without request id are sent as toast messages.
+async fn reply(
+    client: &Client,
+    request_id: Option<String>,
+    result: Result<(), Error>,
+) -> Result<(), Error> {
+    let msg = match (request_id, result) {
+        (_, Err(Error::SendMessage)) => return Err(Error::SendMessage),
+        (Some(request_id), Ok(())) => ServerEvent::Ack(AckResponse { request_id }).to_msg()?,
+        (None, Ok(())) => return Ok(()),
+        (Some(request_id), Err(err)) => {
+            let (status, message) = err.into_error();
+            let rsp = ErrorResponse {
+                request_id,
+                code: status.as_u16(),
+                message,
+            };
+            ServerEvent::Error(rsp).to_msg()?
+        }
+        (None, Err(err)) => {
+            let (_, msg) = err.into_error();
+            ServerEvent::ErrMessage(msg).to_msg()?
+        }
+    };
+    client.send(msg).await?;
+    Ok(())
+}
+
 // ============================== // ClientEvent // ============================== //
 
 #[derive(Deserialize)]
@@ -22,9 +138,60 @@ pub enum ClientEvent {
     #[serde(rename = "initialize")]
     Initialize(InitializeRequest),
 
+    #[serde(rename = "resume")]
+    Resume(ResumeRequest),
+
     #[serde(rename = "new-message")]
     NewMessage(NewMessageRequest),
 
+    #[serde(rename = "forward-messages")]
+    ForwardMessages(ForwardMessagesRequest),
+
+    #[serde(rename = "edit-message")]
+    EditMessage(EditMessageRequest),
+
+    #[serde(rename = "delete-message")]
+    DeleteMessage(DeleteMessageRequest),
+
+    #[serde(rename = "add-reaction")]
+    AddReaction(ReactionRequest),
+
+    #[serde(rename = "remove-reaction")]
+    RemoveReaction(ReactionRequest),
+
+    #[serde(rename = "pin-message")]
+    PinMessage(PinMessageRequest),
+
+    #[serde(rename = "unpin-message")]
+    UnpinMessage(PinMessageRequest),
+
+    #[serde(rename = "save-message")]
+    SaveMessage(SaveMessageRequest),
+
+    #[serde(rename = "unsave-message")]
+    UnsaveMessage(SaveMessageRequest),
+
+    #[serde(rename = "list-messages")]
+    ListMessages(ListMessagesRequest),
+
+    #[serde(rename = "list-replies")]
+    ListReplies(ListRepliesRequest),
+
+    #[serde(rename = "list-edits")]
+    ListEdits(ListEditsRequest),
+
+    #[serde(rename = "mark-read")]
+    MarkRead(MarkReadRequest),
+
+    #[serde(rename = "list-readers")]
+    ListReaders(ListReadersRequest),
+
+    #[serde(rename = "typing-start")]
+    TypingStart(TypingRequest),
+
+    #[serde(rename = "typing-stop")]
+    TypingStop(TypingRequest),
+
     // Room
     #[serde(rename = "new-room")]
     NewRoom(NewRoomRequest),
@@ -61,9 +228,28 @@ pub enum ClientEvent {
 
 impl ClientEvent {
     pub async fn process(self, state: &Arc<AppState>, client: &Client) -> Result<(), Error> {
-        let result = match self {
+        match self {
             ClientEvent::Initialize(_) => message::initialize(state, client).await,
+            ClientEvent::Resume(req) => message::resume(state, client, req).await,
             ClientEvent::NewMessage(req) => message::send_message(state, client, req).await,
+            ClientEvent::ForwardMessages(req) => {
+                message::forward_messages(state, client, req).await
+            }
+            ClientEvent::EditMessage(req) => message::edit_message(state, client, req).await,
+            ClientEvent::DeleteMessage(req) => message::delete_message(state, client, req).await,
+            ClientEvent::AddReaction(req) => message::add_reaction(state, client, req).await,
+            ClientEvent::RemoveReaction(req) => message::remove_reaction(state, client, req).await,
+            ClientEvent::PinMessage(req) => message::pin_message(state, client, req).await,
+            ClientEvent::UnpinMessage(req) => message::unpin_message(state, client, req).await,
+            ClientEvent::SaveMessage(req) => message::save_message(state, client, req).await,
+            ClientEvent::UnsaveMessage(req) => message::unsave_message(state, client, req).await,
+            ClientEvent::ListMessages(req) => message::list_messages(state, client, req).await,
+            ClientEvent::ListReplies(req) => message::list_replies(state, client, req).await,
+            ClientEvent::ListEdits(req) => message::list_edits(state, client, req).await,
+            ClientEvent::MarkRead(req) => message::mark_read(state, client, req).await,
+            ClientEvent::ListReaders(req) => message::list_readers(state, client, req).await,
+            ClientEvent::TypingStart(req) => typing::start_typing(state, client, req).await,
+            ClientEvent::TypingStop(req) => typing::stop_typing(state, client, req).await,
             ClientEvent::NewRoom(req) => room::create_room(state, client, req).await,
             ClientEvent::UpdateRoom(req) => room::update_room(state, client, req).await,
             ClientEvent::DeleteRoom(req) => room::delete_room(state, client, req).await,
@@ -74,20 +260,7 @@ impl ClientEvent {
             ClientEvent::AcceptFriend(req) => friend::accept_friend(state, client, req).await,
             ClientEvent::RefuseFriend(req) => friend::refuse_friend(state, client, req).await,
             ClientEvent::DeleteFriend(req) => friend::delete_friend(state, client, req).await,
-        };
-
-        // send text message of WsError to the user's client
-        if let Err(err) = result {
-            match err {
-                Error::SendMessage => return Err(err),
-                _ => {
-                    let (_, msg) = err.into_error();
-                    let msg = ServerEvent::ErrMessage(msg).to_msg()?;
-                    client.send(msg).await?;
-                }
-            }
         }
-        Ok(())
     }
 }
 
@@ -99,12 +272,72 @@ pub enum ServerEvent {
     #[serde(rename = "toast")]
     ErrMessage(String),
 
+    #[serde(rename = "ack")]
+    Ack(AckResponse),
+
+    #[serde(rename = "error")]
+    Error(ErrorResponse),
+
     #[serde(rename = "initialize")]
     Initialize(InitializeResponse),
 
+    #[serde(rename = "resume")]
+    Resume(ResumeResponse),
+
+    #[serde(rename = "resync")]
+    Resync,
+
     #[serde(rename = "new-message")]
     NewMessage(NewMessageResponse),
 
+    #[serde(rename = "edit-message")]
+    EditMessage(EditMessageResponse),
+
+    #[serde(rename = "delete-message")]
+    DeleteMessage(DeleteMessageResponse),
+
+    #[serde(rename = "add-reaction")]
+    AddReaction(ReactionResponse),
+
+    #[serde(rename = "remove-reaction")]
+    RemoveReaction(ReactionResponse),
+
+    #[serde(rename = "pin-message")]
+    PinMessage(PinMessageResponse),
+
+    #[serde(rename = "unpin-message")]
+    UnpinMessage(UnpinMessageResponse),
+
+    #[serde(rename = "save-message")]
+    SaveMessage(SaveMessageResponse),
+
+    #[serde(rename = "unsave-message")]
+    UnsaveMessage(UnsaveMessageResponse),
+
+    #[serde(rename = "list-messages")]
+    ListMessages(ListMessagesResponse),
+
+    #[serde(rename = "list-replies")]
+    ListReplies(ListRepliesResponse),
+
+    #[serde(rename = "list-edits")]
+    ListEdits(ListEditsResponse),
+
+    #[serde(rename = "mark-read")]
+    MarkRead(MarkReadResponse),
+
+    #[serde(rename = "read-receipt")]
+    ReadReceipt(ReadReceiptResponse),
+
+    #[serde(rename = "list-readers")]
+    ListReaders(ListReadersResponse),
+
+    #[serde(rename = "typing-start")]
+    TypingStart(TypingResponse),
+
+    #[serde(rename = "typing-stop")]
+    TypingStop(TypingResponse),
+
     // Room
     #[serde(rename = "new-room")]
     NewRoom(NewRoomResponse),
@@ -137,11 +370,77 @@ pub enum ServerEvent {
 
     #[serde(rename = "delete-friend")]
     DeleteFriend(DeleteFriendResponse),
+
+    #[serde(rename = "presence")]
+    Presence(PresenceResponse),
 }
 
 impl ServerEvent {
     pub fn to_msg(&self) -> Result<Message, Error> {
-        let msg = serde_json::to_string(self)?;
-        Ok(Message::Text(msg))
+        Message::encode(self)
+    }
+}
+
+// ============================== // Codec // ============================== //
+
+/// Encoding of the events on a websocket, chosen by the sub-protocol
+///
+/// Server events are encoded for both codecs once when they are created,
+/// and the websocket writes the encoding of its client.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Codec {
+    Json,
+    MessagePack,
+}
+
+impl Codec {
+    /// Return the codec of the negotiated sub-protocol
+    pub fn from_protocol(protocol: Option<&str>) -> Self {
+        match protocol {
+            Some(WS_MSGPACK_PROTOCOL_KEY) => Self::MessagePack,
+            _ => Self::Json,
+        }
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_request() {
+        let text = r#"{"action":"typing-start","data":{"roomId":3},"requestId":"r1"}"#;
+        let req = serde_json::from_str::<ClientRequest>(text).unwrap();
+        assert_eq!(req.request_id.as_deref(), Some("r1"));
+        assert!(matches!(req.event, ClientEvent::TypingStart(ref x) if x.room_id == 3));
+
+        let text = r#"{"action":"initialize","data":{}}"#;
+        let req = serde_json::from_str::<ClientRequest>(text).unwrap();
+        assert!(req.request_id.is_none());
+        assert!(matches!(req.event, ClientEvent::Initialize(_)));
+
+        let text = r#"{"action":"unknown","data":{},"requestId":"r2"}"#;
+        assert!(serde_json::from_str::<ClientRequest>(text).is_err());
+        let id = serde_json::from_str::<RequestId>(text).unwrap();
+        assert_eq!(id.request_id.as_deref(), Some("r2"));
+    }
+
+    #[test]
+    fn test_msgpack_codec() {
+        let value = serde_json::json!({
+            "action": "typing-start",
+            "data": { "roomId": 3 },
+            "requestId": "r1",
+        });
+        let data = rmp_serde::to_vec_named(&value).unwrap();
+        let req = rmp_serde::from_slice::<ClientRequest>(&data).unwrap();
+        assert_eq!(req.request_id.as_deref(), Some("r1"));
+        assert!(matches!(req.event, ClientEvent::TypingStart(ref x) if x.room_id == 3));
+
+        let rsp = ServerEvent::Resync.to_msg().unwrap();
+        let value = rmp_serde::from_slice::<serde_json::Value>(&rsp.to_binary()).unwrap();
+        assert_eq!(value, serde_json::json!({ "action": "resync" }));
     }
 }
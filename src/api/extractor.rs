@@ -72,7 +72,7 @@ where
             .ok_or(Error::Unauthorized)?;
 
         match authorization.split_once(' ') {
-            Some((name, content)) if name == "Bearer" => Ok(BearerToken(content.to_owned())),
+            Some(("Bearer", content)) => Ok(BearerToken(content.to_owned())),
             _ => Err(Error::Unauthorized),
         }
     }
@@ -2,14 +2,15 @@
 
 use super::{
     event::ServerEvent, AcceptFriendRequest, AcceptFriendResponse, AddFriendRequest,
-    AddFriendResponse, AppState, DeleteFriendRequest, DeleteFriendResponse, RefuseFriendRequest,
-    RefuseFriendResponse,
+    AddFriendResponse, AppState, DeleteFriendRequest, DeleteFriendResponse, PresenceResponse,
+    RefuseFriendRequest, RefuseFriendResponse,
 };
 use crate::{
     conn::Client,
     core::{constant::STATUS_DELETED, Error},
 };
 use std::sync::Arc;
+use time::OffsetDateTime;
 use validator::Validate;
 
 pub async fn add_friend(
@@ -74,8 +75,10 @@ pub async fn accept_friend(
     // update friend status and add friend to the private room
     state.db.accept_friend(&friend).await?;
 
-    let (requester, addressee) = state.db.get_friend_info(&friend).await?;
+    let (mut requester, mut addressee) = state.db.get_friend_info(&friend).await?;
     let (room0, room1) = state.db.get_friend_room(&friend).await?;
+    requester.online = state.hub.is_online(friend.requester_id).await;
+    addressee.online = true;
 
     // join friends in the hub room
     let user_ids = vec![friend.requester_id, friend.addressee_id];
@@ -171,3 +174,29 @@ pub async fn delete_friend(
 
     Ok(())
 }
+
+/// Notice the accepted friends that the user goes online or offline
+pub async fn notify_presence(
+    state: &Arc<AppState>,
+    user_id: i64,
+    online: bool,
+) -> Result<(), Error> {
+    let last_seen = OffsetDateTime::now_utc();
+    if !online {
+        state.db.update_last_seen(user_id, last_seen).await?;
+    }
+
+    // presence is not kept for the offline friends
+    let friend_ids = state.db.get_friend_ids(user_id).await?;
+    let online_ids = state.hub.online_users(&friend_ids).await?;
+
+    let rsp = PresenceResponse {
+        user_id,
+        online,
+        last_seen,
+    };
+    let msg = ServerEvent::Presence(rsp).to_msg()?;
+    state.hub.notify(&online_ids, msg).await?;
+
+    Ok(())
+}
@@ -27,7 +27,7 @@ pub async fn add_members(
 
     // insert members into database
     let members = state.db.add_members(&req).await?;
-    let users_id = members.iter().map(|x| x.id).collect();
+    let users_id: Vec<i64> = members.iter().map(|x| x.id).collect();
 
     // notice room members
     let rsp = AddMembersResponse { room_id, members };
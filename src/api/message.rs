@@ -1,15 +1,23 @@
 //! Handlers for messages
 
 use super::{
-    dto::{InitializeResponse, NewMessageRequest, SendFileResponse},
+    dto::{
+        DeleteMessageRequest, EditMessageRequest, ForwardMessagesRequest, InitializeResponse,
+        ListEditsRequest, ListEditsResponse, ListMessagesRequest, ListMessagesResponse,
+        ListReadersRequest, ListReadersResponse, ListRepliesRequest, ListRepliesResponse,
+        ListSavedRequest, ListSavedResponse, MarkReadRequest, NewMessageRequest, PinMessageRequest,
+        PinMessageResponse, ReactionRequest, ReadReceiptResponse, ResumeRequest,
+        SaveMessageRequest, SearchMessagesRequest, SearchMessagesResponse, SendFileResponse,
+        UnpinMessageResponse, UnsaveMessageResponse,
+    },
     event::ServerEvent,
-    extractor::AuthGuard,
-    AppState, NewMessageResponse,
+    extractor::{AuthGuard, ValidQuery},
+    friend, AppState, NewMessageResponse,
 };
 use crate::{
-    conn::Client,
+    conn::{Client, Message},
     core::{
-        constant::{IMAGE_KEY, KIND_FILE, KIND_IMAGE},
+        constant::{IMAGE_KEY, KIND_FILE, KIND_IMAGE, KIND_TEXT, RANK_OWNER, STATUS_ACCEPTED},
         Error,
     },
     util,
@@ -17,7 +25,7 @@ use crate::{
 use axum::{
     body::Bytes,
     extract::{DefaultBodyLimit, Multipart, State},
-    routing::post,
+    routing::{get, post},
     BoxError, Json, Router,
 };
 use futures::{Stream, TryStreamExt};
@@ -26,6 +34,7 @@ use std::{
     path::{Path, PathBuf},
     sync::Arc,
 };
+use time::{Duration, OffsetDateTime};
 use tokio::{fs::File, io::BufWriter};
 use tokio_util::io::StreamReader;
 use validator::Validate;
@@ -33,9 +42,43 @@ use validator::Validate;
 pub fn router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/message/file", post(send_file))
+        .route("/message/history", get(get_messages))
+        .route("/message/search", get(search_messages))
+        .route("/message/saved", get(get_saved_messages))
         .layer(DefaultBodyLimit::max(150 * 1024 * 1024))
 }
 
+async fn get_messages(
+    State(state): State<Arc<AppState>>,
+    AuthGuard(claims): AuthGuard,
+    ValidQuery(req): ValidQuery<ListMessagesRequest>,
+) -> Result<Json<ListMessagesResponse>, Error> {
+    // check if room exist and user is a member
+    state.db.get_rank(claims.user_id, req.room_id).await?;
+
+    let rsp = state.db.list_messages(claims.user_id, &req).await?;
+    Ok(Json(rsp))
+}
+
+async fn search_messages(
+    State(state): State<Arc<AppState>>,
+    AuthGuard(claims): AuthGuard,
+    ValidQuery(req): ValidQuery<SearchMessagesRequest>,
+) -> Result<Json<SearchMessagesResponse>, Error> {
+    // only the rooms of the user are searched
+    let rsp = state.db.search_messages(claims.user_id, &req).await?;
+    Ok(Json(rsp))
+}
+
+async fn get_saved_messages(
+    State(state): State<Arc<AppState>>,
+    AuthGuard(claims): AuthGuard,
+    ValidQuery(req): ValidQuery<ListSavedRequest>,
+) -> Result<Json<ListSavedResponse>, Error> {
+    let rsp = state.db.list_saved(claims.user_id, &req).await?;
+    Ok(Json(rsp))
+}
+
 async fn send_file(
     State(state): State<Arc<AppState>>,
     AuthGuard(claims): AuthGuard,
@@ -86,7 +129,7 @@ where
 {
     async {
         // convert the stream into an `AsyncRead`.
-        let body_with_io_error = stream.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+        let body_with_io_error = stream.map_err(|err| io::Error::other(err));
         let body_reader = StreamReader::new(body_with_io_error);
         futures::pin_mut!(body_reader);
 
@@ -103,17 +146,71 @@ where
 pub async fn initialize(state: &Arc<AppState>, client: &Client) -> Result<(), Error> {
     // get room information from database
     let rooms = state.db.get_user_rooms(client.user_id()).await?;
-    let friends = state.db.get_user_friends(client.user_id()).await?;
+    let mut friends = state.db.get_user_friends(client.user_id()).await?;
 
     // create connections to the room channels
     let rooms_id: Vec<i64> = rooms.iter().map(|room| room.id).collect();
-    state.hub.connect(client, rooms_id).await?;
+    let (first, resume) = state.hub.connect(client, rooms_id).await?;
+
+    // mark the accepted friends who are online
+    let accepted: Vec<i64> = friends
+        .iter()
+        .filter(|f| f.status == STATUS_ACCEPTED)
+        .map(|f| f.id)
+        .collect();
+    let online = state.hub.online_users(&accepted).await?;
+    for friend in friends.iter_mut() {
+        friend.online = online.contains(&friend.id);
+    }
 
     // send rooms and friends info to the client socket
-    let rsp = InitializeResponse { rooms, friends };
+    let rsp = InitializeResponse {
+        rooms,
+        friends,
+        resume_token: resume.resume_token,
+        cursor: resume.cursor,
+    };
     let msg = ServerEvent::Initialize(rsp).to_msg()?;
     client.send(msg).await?;
 
+    // deliver the events sent while the user had no client
+    for event in state.db.take_offline_events(client.user_id()).await? {
+        client.send(Message::from_text(event)?).await?;
+    }
+
+    // notice friends if this is the first client of the user
+    if first {
+        friend::notify_presence(state, client.user_id(), true).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn resume(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ResumeRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // replay the missed events, or the client has to initialize again
+    let (first, rsp) = state
+        .hub
+        .resume(client, &req.resume_token, req.cursor)
+        .await?;
+    let msg = ServerEvent::Resume(rsp).to_msg()?;
+    client.send(msg).await?;
+
+    // deliver the events sent while the user had no client
+    for event in state.db.take_offline_events(client.user_id()).await? {
+        client.send(Message::from_text(event)?).await?;
+    }
+
+    // notice friends if this is the first client of the user
+    if first {
+        friend::notify_presence(state, client.user_id(), true).await?;
+    }
+
     Ok(())
 }
 
@@ -129,14 +226,436 @@ pub async fn send_message(
         return Err(Error::Forbidden);
     }
 
-    // create message and store in redis
-    let message = state.db.cache_message(client.user_id(), req).await?;
+    // save message in database and push it to the cache
+    let client_id = req.client_id.clone();
+    let (message, new) = state.db.create_message(client.user_id(), req).await?;
     let room_id = message.room_id;
-
-    // send message to the room
-    let rsp = NewMessageResponse { message };
+    let rsp = NewMessageResponse { message, client_id };
     let msg = ServerEvent::NewMessage(rsp).to_msg()?;
-    state.hub.broadcast(room_id, msg).await?;
+
+    // send message to the room, or only echo a resent one to the client
+    if new {
+        state.hub.broadcast(room_id, msg).await?;
+    } else {
+        client.send(msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn forward_messages(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ForwardMessagesRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+    let user_id = client.user_id();
+
+    // check whether user is in the rooms of the source messages
+    let sources = state.db.get_forward_sources(&req.messages_id).await?;
+    for source in &sources {
+        if !state.hub.is_user_in(user_id, source.room_id).await {
+            return Err(Error::Forbidden);
+        }
+    }
+
+    // check whether user is a member of the target rooms
+    for &room_id in &req.rooms_id {
+        state.db.get_rank(user_id, room_id).await?;
+    }
+
+    // save the copies in database and send them to the target rooms
+    let messages = state
+        .db
+        .forward_messages(user_id, sources, &req.rooms_id)
+        .await?;
+    for message in messages {
+        let room_id = message.room_id;
+        let rsp = NewMessageResponse {
+            message,
+            client_id: None,
+        };
+        let msg = ServerEvent::NewMessage(rsp).to_msg()?;
+        state.hub.broadcast(room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn edit_message(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: EditMessageRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // only the sender can edit a text message in the time window
+    let message = state.db.get_message(req.message_id).await?;
+    if message.deleted {
+        return Err(Error::NotFound);
+    }
+    if !state
+        .hub
+        .is_user_in(client.user_id(), message.room_id)
+        .await
+    {
+        return Err(Error::Forbidden);
+    }
+    if message.sender_id != client.user_id() || message.kind != KIND_TEXT {
+        return Err(Error::Forbidden);
+    }
+    let window = Duration::minutes(state.config.message_edit_minutes);
+    if OffsetDateTime::now_utc() - message.send_at > window {
+        return Err(Error::Forbidden);
+    }
+
+    // update the message content in database
+    let rsp = state.db.edit_message(&req).await?;
+
+    // notice all room members
+    let msg = ServerEvent::EditMessage(rsp).to_msg()?;
+    state.hub.broadcast(message.room_id, msg).await?;
+
+    Ok(())
+}
+
+pub async fn delete_message(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: DeleteMessageRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // only the sender or the room owner can delete the message
+    let message = state.db.get_message(req.message_id).await?;
+    if message.deleted {
+        return Err(Error::NotFound);
+    }
+    if !state
+        .hub
+        .is_user_in(client.user_id(), message.room_id)
+        .await
+    {
+        return Err(Error::Forbidden);
+    }
+    if message.sender_id != client.user_id() {
+        let rank = state.db.get_rank(client.user_id(), message.room_id).await?;
+        if rank != RANK_OWNER {
+            return Err(Error::Forbidden);
+        }
+    }
+
+    // recall the message in database
+    let (rsp, unpin) = state.db.delete_message(message.id).await?;
+
+    // notice all room members, also of the pin removed with the message
+    let msg = ServerEvent::DeleteMessage(rsp).to_msg()?;
+    state.hub.broadcast(message.room_id, msg).await?;
+    if let Some(rsp) = unpin {
+        let msg = ServerEvent::UnpinMessage(rsp).to_msg()?;
+        state.hub.broadcast(message.room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn add_reaction(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ReactionRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if message exist and user is in the room
+    let message = state.db.get_message(req.message_id).await?;
+    if message.deleted {
+        return Err(Error::NotFound);
+    }
+    if !state
+        .hub
+        .is_user_in(client.user_id(), message.room_id)
+        .await
+    {
+        return Err(Error::Forbidden);
+    }
+
+    // save the reaction and notice all room members if it is new
+    let (rsp, added) = state
+        .db
+        .add_reaction(client.user_id(), message.room_id, &req)
+        .await?;
+    if added {
+        let msg = ServerEvent::AddReaction(rsp).to_msg()?;
+        state.hub.broadcast(message.room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn remove_reaction(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ReactionRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if message exist and user is in the room
+    let message = state.db.get_message(req.message_id).await?;
+    if !state
+        .hub
+        .is_user_in(client.user_id(), message.room_id)
+        .await
+    {
+        return Err(Error::Forbidden);
+    }
+
+    // remove the reaction and notice all room members if it existed
+    let (rsp, removed) = state
+        .db
+        .remove_reaction(client.user_id(), message.room_id, &req)
+        .await?;
+    if removed {
+        let msg = ServerEvent::RemoveReaction(rsp).to_msg()?;
+        state.hub.broadcast(message.room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn pin_message(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: PinMessageRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // only the room owner can pin a message
+    let message = state.db.get_message(req.message_id).await?;
+    if message.deleted {
+        return Err(Error::NotFound);
+    }
+    let rank = state.db.get_rank(client.user_id(), message.room_id).await?;
+    if rank != RANK_OWNER {
+        return Err(Error::Forbidden);
+    }
+
+    // save the pin and notice all room members if it is new
+    let pin = state
+        .db
+        .pin_message(client.user_id(), message.room_id, message.id)
+        .await?;
+    if let Some(pin) = pin {
+        let rsp = PinMessageResponse {
+            room_id: message.room_id,
+            pin,
+        };
+        let msg = ServerEvent::PinMessage(rsp).to_msg()?;
+        state.hub.broadcast(message.room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn unpin_message(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: PinMessageRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // only the room owner can unpin a message
+    let room_id = state.db.get_message_room(req.message_id).await?;
+    let rank = state.db.get_rank(client.user_id(), room_id).await?;
+    if rank != RANK_OWNER {
+        return Err(Error::Forbidden);
+    }
+
+    // remove the pin and notice all room members if it existed
+    if state.db.unpin_message(room_id, req.message_id).await? {
+        let rsp = UnpinMessageResponse {
+            room_id,
+            message_id: req.message_id,
+        };
+        let msg = ServerEvent::UnpinMessage(rsp).to_msg()?;
+        state.hub.broadcast(room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn save_message(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: SaveMessageRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if message exist and user is in the room
+    let message = state.db.get_message(req.message_id).await?;
+    if message.deleted {
+        return Err(Error::NotFound);
+    }
+    if !state
+        .hub
+        .is_user_in(client.user_id(), message.room_id)
+        .await
+    {
+        return Err(Error::Forbidden);
+    }
+
+    // save the message and sync to all clients of the user
+    if let Some(rsp) = state.db.add_saved(client.user_id(), message.id).await? {
+        let msg = ServerEvent::SaveMessage(rsp).to_msg()?;
+        state.hub.broadcast(client.room_id(), msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn unsave_message(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: SaveMessageRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // remove the message and sync to all clients of the user
+    if state
+        .db
+        .remove_saved(client.user_id(), req.message_id)
+        .await?
+    {
+        let rsp = UnsaveMessageResponse {
+            message_id: req.message_id,
+        };
+        let msg = ServerEvent::UnsaveMessage(rsp).to_msg()?;
+        state.hub.broadcast(client.room_id(), msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn list_messages(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ListMessagesRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if room exist and user is a member
+    state.db.get_rank(client.user_id(), req.room_id).await?;
+
+    // send the page of messages to the client
+    let rsp = state.db.list_messages(client.user_id(), &req).await?;
+    let msg = ServerEvent::ListMessages(rsp).to_msg()?;
+    client.send(msg).await?;
+
+    Ok(())
+}
+
+pub async fn mark_read(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: MarkReadRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // update the read marker of the user in database
+    let (rsp, advanced) = state.db.mark_read(client.user_id(), &req).await?;
+    let last_read_seq = rsp.last_read_seq;
+
+    // sync the read marker to all clients of the user
+    let msg = ServerEvent::MarkRead(rsp).to_msg()?;
+    state.hub.broadcast(client.room_id(), msg).await?;
+
+    // send read receipt to the room members
+    if advanced {
+        let rsp = ReadReceiptResponse {
+            room_id: req.room_id,
+            member_id: client.user_id(),
+            last_read_seq,
+        };
+        let msg = ServerEvent::ReadReceipt(rsp).to_msg()?;
+        state.hub.broadcast(req.room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn list_replies(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ListRepliesRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if message exist and user is a member of the room
+    let room_id = state.db.get_message_room(req.message_id).await?;
+    state.db.get_rank(client.user_id(), room_id).await?;
+
+    // send the thread of the message to the client
+    let replies = state
+        .db
+        .list_replies(client.user_id(), req.message_id)
+        .await?;
+    let rsp = ListRepliesResponse {
+        message_id: req.message_id,
+        room_id,
+        replies,
+    };
+    let msg = ServerEvent::ListReplies(rsp).to_msg()?;
+    client.send(msg).await?;
+
+    Ok(())
+}
+
+pub async fn list_edits(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ListEditsRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if message exist and user is a member of the room
+    let message = state.db.get_message(req.message_id).await?;
+    if message.deleted {
+        return Err(Error::NotFound);
+    }
+    state.db.get_rank(client.user_id(), message.room_id).await?;
+
+    // send the earlier contents of the message to the client
+    let edits = state.db.get_edits(req.message_id).await?;
+    let rsp = ListEditsResponse {
+        message_id: req.message_id,
+        room_id: message.room_id,
+        edits,
+    };
+    let msg = ServerEvent::ListEdits(rsp).to_msg()?;
+    client.send(msg).await?;
+
+    Ok(())
+}
+
+pub async fn list_readers(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: ListReadersRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check if message exist and user is a member of the room
+    let room_id = state.db.get_message_room(req.message_id).await?;
+    state.db.get_rank(client.user_id(), room_id).await?;
+
+    // send the readers of the message to the client
+    let readers = state.db.get_readers(req.message_id).await?;
+    let rsp = ListReadersResponse {
+        message_id: req.message_id,
+        room_id,
+        readers,
+    };
+    let msg = ServerEvent::ListReaders(rsp).to_msg()?;
+    client.send(msg).await?;
 
     Ok(())
 }
@@ -12,10 +12,12 @@ mod member;
 mod message;
 mod room;
 mod router;
+mod typing;
 mod user;
 mod websocket;
 
 pub use dto::*;
+pub use event::ServerEvent;
 pub use router::make_app;
 
 /// The data that is shared across the processes.
@@ -23,17 +25,38 @@ pub struct AppState {
     db: Store,
     hub: Hub,
     jwt: JwtToken,
+    handshake: websocket::Handshake,
     config: Config,
 }
 
 impl AppState {
-    async fn new(config: Config) -> Arc<Self> {
+    pub async fn new(config: Config) -> Arc<Self> {
+        let db = Store::new(&config).await;
         let state = AppState {
-            db: Store::new(&config).await,
-            hub: Hub::default(),
+            hub: Hub::new(db.clone(), &config),
+            db,
             jwt: JwtToken::new(&config),
+            handshake: websocket::Handshake::new(&config),
             config,
         };
-        Arc::new(state)
+        let state = Arc::new(state);
+
+        // stop the typing of the users who do not refresh it
+        {
+            let state = state.clone();
+            tokio::spawn(async move { state.hub.expire_typing().await });
+        }
+
+        // relay the events of the other nodes to the local rooms
+        if state.config.hub_fanout {
+            let state = state.clone();
+            tokio::spawn(async move { state.hub.relay().await });
+        }
+        state
+    }
+
+    /// Close the websockets before the server exits
+    pub async fn shutdown(&self) {
+        self.hub.shutdown().await;
     }
 }
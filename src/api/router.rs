@@ -1,23 +1,20 @@
 //! Defines the router of the server.
 
 use super::{auth, message, room, user, websocket, AppState};
-use crate::Config;
 use axum::{
     error_handling::HandleErrorLayer,
     http::{header, Method, StatusCode},
     BoxError, Router,
 };
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tower::{timeout::TimeoutLayer, ServiceBuilder};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
 /// Create router of the application.
 ///
-/// - `config`: The global configure of the application.
-pub async fn make_app(config: Config) -> Router {
-    let state = AppState::new(config).await;
-
+/// - `state`: The data shared across the processes.
+pub fn make_app(state: Arc<AppState>) -> Router {
     Router::new()
         .merge(websocket::router())
         .nest(
@@ -0,0 +1,55 @@
+//! Handlers for typing indicators
+
+use super::{event::ServerEvent, AppState, TypingRequest, TypingResponse};
+use crate::{conn::Client, core::Error};
+use std::sync::Arc;
+use validator::Validate;
+
+pub async fn start_typing(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: TypingRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check whether user is in the room
+    let room_id = req.room_id;
+    let user_id = client.user_id();
+    if !state.hub.is_user_in(user_id, room_id).await {
+        return Err(Error::Forbidden);
+    }
+
+    // notice the room members only when the user starts typing, and the
+    // hub stops it if the user does not refresh it in time
+    if state.hub.start_typing(room_id, user_id).await? == Some(true) {
+        let rsp = TypingResponse { room_id, user_id };
+        let msg = ServerEvent::TypingStart(rsp).to_msg()?;
+        state.hub.broadcast(room_id, msg).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn stop_typing(
+    state: &Arc<AppState>,
+    client: &Client,
+    req: TypingRequest,
+) -> Result<(), Error> {
+    req.validate()?;
+
+    // check whether user is in the room
+    let room_id = req.room_id;
+    let user_id = client.user_id();
+    if !state.hub.is_user_in(user_id, room_id).await {
+        return Err(Error::Forbidden);
+    }
+
+    // notice the room members
+    if state.hub.stop_typing(room_id, user_id).await? {
+        let rsp = TypingResponse { room_id, user_id };
+        let msg = ServerEvent::TypingStop(rsp).to_msg()?;
+        state.hub.broadcast(room_id, msg).await?;
+    }
+
+    Ok(())
+}
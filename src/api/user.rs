@@ -55,14 +55,13 @@ async fn update_user(
     AuthGuard(claims): AuthGuard,
     ValidJson(req): ValidJson<UpdateUserRequest>,
 ) -> Result<Json<UpdateUserResponse>, Error> {
-    if claims.role != ROLE_ADMIN {
-        if req.role.is_some()
+    if claims.role != ROLE_ADMIN
+        && (req.role.is_some()
             || req.deleted.is_some()
             || req.password.is_some()
-            || req.user_id != claims.user_id
-        {
-            return Err(Error::Forbidden);
-        }
+            || req.user_id != claims.user_id)
+    {
+        return Err(Error::Forbidden);
     }
     let user = state.db.update_user(&req).await?;
     let rsp = UpdateUserResponse { user };
@@ -117,7 +116,7 @@ async fn list_users(
     AdminGuard(_): AdminGuard,
     ValidQuery(req): ValidQuery<ListUsersRequest>,
 ) -> Result<Json<ListUsersResponse>, Error> {
-    let (total, users) = state.db.list_users(&req.into()).await?;
+    let (total, users) = state.db.list_users(&req).await?;
     let rsp = ListUsersResponse { total, users };
     Ok(Json(rsp))
 }
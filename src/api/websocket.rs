@@ -1,56 +1,162 @@
 //! Handlers for websocket
 
-use super::{extractor::WsGuard, AppState};
-use crate::api::event::ClientEvent;
-use crate::core::constant::{CHAN_CAPACITY, WS_SUB_PROTOCOL_KEY};
-use crate::{conn::Client, util::token::Claims};
-use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::State;
-use axum::{response::IntoResponse, routing::get, Router};
-use futures::{sink::SinkExt, stream::StreamExt};
+use super::{extractor::WsGuard, friend, AppState};
+use crate::api::event::{ClientRequest, Codec};
+use crate::core::constant::{WS_MSGPACK_PROTOCOL_KEY, WS_SUB_PROTOCOL_KEY};
+use crate::{
+    conn::{Client, Compression, Message},
+    core::Error,
+    util::token::Claims,
+    Config,
+};
+use axum::extract::{Request, State};
+use axum::{body::Body, http::Method, response::Response, routing::get, Router};
+use bytes::BytesMut;
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use ratchet_core::{
+    self as ratchet, server, CloseCode, CloseReason, Role, Sender, SubprotocolRegistry, WebSocket,
+    WebSocketConfig, WebSocketStream,
+};
+use ratchet_deflate::{Deflate, DeflateExtProvider};
+use ratchet_ext::ExtensionEncoder;
 use std::sync::Arc;
-use tokio::sync::mpsc;
 use tokio::time::{self, Duration};
 
 pub fn router() -> Router<Arc<AppState>> {
     Router::new().route("/ws", get(ws_handler))
 }
 
+/// What the server offers in the websocket handshake
+pub struct Handshake {
+    protocols: SubprotocolRegistry,
+    deflate: Option<DeflateExtProvider>,
+}
+
+impl Handshake {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            protocols: SubprotocolRegistry::new([WS_SUB_PROTOCOL_KEY, WS_MSGPACK_PROTOCOL_KEY])
+                .expect("sub-protocols must be valid header values"),
+            // permessage-deflate is offered by the client in Sec-WebSocket-Extensions
+            deflate: config.ws_compression.then(DeflateExtProvider::default),
+        }
+    }
+}
+
 async fn ws_handler(
-    ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
     WsGuard(claims): WsGuard,
-) -> impl IntoResponse {
-    ws.protocols([WS_SUB_PROTOCOL_KEY])
-        .on_upgrade(move |socket| websocket(socket, state, claims))
+    mut req: Request,
+) -> Result<Response, Error> {
+    // the route also takes HEAD, which can not be upgraded
+    if req.method() != Method::GET {
+        return Err(Error::MethodNotAllowed);
+    }
+    if state.hub.is_closed() {
+        return Err(Error::ShuttingDown);
+    }
+
+    let handshake = &state.handshake;
+    let parts = server::response_from_headers(
+        req.headers(),
+        handshake.deflate.as_ref(),
+        &handshake.protocols,
+    )
+    .map_err(|e| {
+        tracing::debug!("websocket handshake failed: {}", e);
+        Error::BadRequest
+    })?;
+
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    let (protocol, deflate) = (parts.subprotocol, parts.extension);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => websocket(upgraded, protocol, deflate, state, claims).await,
+            Err(e) => tracing::error!("failed to upgrade websocket: {}", e),
+        }
+    });
+
+    let (head, ()) = parts.response.into_parts();
+    Ok(Response::from_parts(head, Body::empty()))
 }
 
-async fn websocket(socket: WebSocket, state: Arc<AppState>, claims: Claims) {
+async fn websocket(
+    upgraded: Upgraded,
+    protocol: Option<String>,
+    deflate: Option<Deflate>,
+    state: Arc<AppState>,
+    claims: Claims,
+) {
+    let codec = Codec::from_protocol(protocol.as_deref());
+    let compression = Compression::new(
+        deflate,
+        state.config.ws_compression_min_size,
+        state.hub.compress_stats(),
+    );
+    let socket = WebSocket::from_upgraded(
+        WebSocketConfig::default(),
+        TokioIo::new(upgraded),
+        Some(compression),
+        BytesMut::new(),
+        Role::Server,
+    );
+
     // by splitting, we can send and receive at the same time
-    let (mut sender, mut receiver) = socket.split();
-
-    // create a mpsc channel for passing message
-    let (tx, mut rx) = mpsc::channel(CHAN_CAPACITY);
-    let client = Client::new(claims.user_id, claims.room_id, tx);
-
-    // this task will receive message from mpsc channel and send to client
-    let mut send_task = tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(15));
-        loop {
-            tokio::select! {
-                Some(msg) = rx.recv() => {
-                    if sender.send(msg).await.is_err() {
-                        break;
+    let Ok((mut sender, mut receiver)) = socket.split() else {
+        return;
+    };
+
+    // create a mailbox for passing message
+    let client = Client::new(claims.user_id, claims.room_id, state.hub.mailbox());
+
+    // this task will receive message from the mailbox and send to client
+    let mut send_task = {
+        let client = client.clone();
+        let mut closing = state.hub.on_closing();
+        // the hub may have closed after the upgrade was accepted
+        if state.hub.is_closed() {
+            closing.mark_changed();
+        }
+        let reason = format!(
+            "server restarting, reconnect in {} seconds",
+            state.config.reconnect_seconds
+        );
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(15));
+            loop {
+                tokio::select! {
+                    msg = client.recv() => {
+                        // the mailbox is closed if the client is too slow
+                        let Some(msg) = msg else {
+                            break;
+                        };
+                        if write(&mut sender, codec, msg).await.is_err() {
+                            break;
+                        }
                     }
-                }
-                _ = interval.tick() => {
-                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                    _ = interval.tick() => {
+                        if sender.write_ping(b"").await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = closing.changed() => {
+                        // deliver the pending events before closing
+                        while let Some(msg) = client.try_recv() {
+                            if write(&mut sender, codec, msg).await.is_err() {
+                                break;
+                            }
+                        }
+
+                        let reason = CloseReason::new(CloseCode::Restarting, Some(reason));
+                        let _ = sender.close(reason).await;
                         break;
                     }
                 }
             }
-        }
-    });
+        })
+    };
 
     // this task will receive client message and process
     let mut recv_task = {
@@ -58,20 +164,36 @@ async fn websocket(socket: WebSocket, state: Arc<AppState>, claims: Claims) {
         let client = client.clone();
 
         tokio::spawn(async move {
-            while let Some(Ok(msg)) = receiver.next().await {
+            let mut buf = BytesMut::new();
+            while let Ok(msg) = receiver.read(&mut buf).await {
                 match msg {
-                    Message::Text(text) => {
+                    ratchet::Message::Text => {
+                        let Ok(text) = std::str::from_utf8(&buf) else {
+                            break;
+                        };
                         tracing::debug!("event: {}", text);
-                        if let Ok(event) = serde_json::from_str::<ClientEvent>(&text) {
-                            if event.process(&state, &client).await.is_err() {
-                                break;
-                            }
+                        if ClientRequest::process_text(text, &state, &client)
+                            .await
+                            .is_err()
+                        {
+                            break;
                         }
                     }
-                    Message::Close(_) => break,
-                    Message::Pong(_) => {}
-                    _ => {}
+                    ratchet::Message::Binary if codec == Codec::MessagePack => {
+                        tracing::debug!("event: {} bytes", buf.len());
+                        if ClientRequest::process_binary(&buf, &state, &client)
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    ratchet::Message::Binary => {}
+                    ratchet::Message::Close(_) => break,
+                    // a control frame may arrive between the fragments of a message
+                    ratchet::Message::Ping(_) | ratchet::Message::Pong(_) => continue,
                 }
+                buf.clear();
             }
         })
     };
@@ -81,7 +203,33 @@ async fn websocket(socket: WebSocket, state: Arc<AppState>, claims: Claims) {
         _ = (&mut recv_task) => send_task.abort(),
     }
 
-    // Disconnecting the channels
-    let _ = state.hub.disconnect(&client).await;
+    // Disconnecting the channels and notice friends if the user goes offline
+    if let Ok(true) = state.hub.disconnect(&client).await {
+        let _ = friend::notify_presence(&state, client.user_id(), false).await;
+
+        // drop the buffered events if the user does not come back in time
+        let grace = Duration::from_secs(state.config.resume_grace_seconds);
+        let user_id = client.user_id();
+        tokio::spawn(async move {
+            time::sleep(grace).await;
+            let _ = state.hub.expire_user(user_id, grace).await;
+        });
+    }
     tracing::debug!("socket disconnect {}:{}", client.user_id(), client.id());
 }
+
+/// Write the message to the websocket in the encoding of the client
+async fn write<S, E>(
+    sender: &mut Sender<S, E>,
+    codec: Codec,
+    msg: Message,
+) -> Result<(), ratchet::Error>
+where
+    S: WebSocketStream,
+    E: ExtensionEncoder,
+{
+    match codec {
+        Codec::Json => sender.write_text(msg.to_text()).await,
+        Codec::MessagePack => sender.write_binary(msg.to_binary()).await,
+    }
+}
@@ -1,6 +1,7 @@
+use super::mailbox::Mailbox;
+use super::message::Message;
 use crate::core::Error;
-use axum::extract::ws::Message;
-use tokio::sync::mpsc;
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// A Client with a connection of user websocket
@@ -9,16 +10,16 @@ pub struct Client {
     id: Uuid,
     user_id: i64,
     room_id: i64,
-    tx: mpsc::Sender<Message>,
+    mailbox: Arc<Mailbox>,
 }
 
 impl Client {
-    pub fn new(user_id: i64, room_id: i64, tx: mpsc::Sender<Message>) -> Self {
+    pub fn new(user_id: i64, room_id: i64, mailbox: Mailbox) -> Self {
         Self {
             id: Uuid::new_v4(),
             user_id,
             room_id,
-            tx,
+            mailbox: Arc::new(mailbox),
         }
     }
 
@@ -37,14 +38,28 @@ impl Client {
         self.room_id
     }
 
-    /// Return channel of the client
-    pub fn tx(&self) -> mpsc::Sender<Message> {
-        self.tx.clone()
-    }
-
     /// Send message to the client
     pub async fn send(&self, msg: Message) -> Result<(), Error> {
-        self.tx.send(msg).await?;
-        Ok(())
+        self.mailbox.send(msg).await
+    }
+
+    /// Send event of the rooms to the client without waiting
+    pub fn push(&self, msg: Message) {
+        self.mailbox.push(msg);
+    }
+
+    /// Send the replayed events to the client without waiting
+    pub fn extend(&self, msgs: Vec<Message>) {
+        self.mailbox.extend(msgs);
+    }
+
+    /// Receive the next message to write to the websocket
+    pub async fn recv(&self) -> Option<Message> {
+        self.mailbox.recv().await
+    }
+
+    /// Receive the next message if there is one
+    pub fn try_recv(&self) -> Option<Message> {
+        self.mailbox.try_recv()
     }
 }
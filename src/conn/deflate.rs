@@ -0,0 +1,185 @@
+use super::stats::CompressStats;
+use bytes::BytesMut;
+use ratchet_deflate::{Deflate, DeflateDecoder, DeflateEncoder, DeflateExtensionError};
+use ratchet_ext::{
+    Extension, ExtensionDecoder, ExtensionEncoder, FrameHeader, RsvBits, SplittableExtension,
+};
+use std::sync::Arc;
+
+/// permessage-deflate (RFC 7692) of a websocket, skipping the small frames
+///
+/// The extension is installed on every websocket, and only the frames of
+/// the websockets that negotiated compression are counted in the stats.
+#[derive(Debug)]
+pub struct Compression {
+    compressor: Compressor,
+    decoder: Option<DeflateDecoder>,
+}
+
+impl Compression {
+    /// Wrap the negotiated permessage-deflate, if any
+    pub fn new(deflate: Option<Deflate>, min_size: usize, stats: Arc<CompressStats>) -> Self {
+        let (encoder, decoder) = deflate.map(SplittableExtension::split).unzip();
+        Self {
+            compressor: Compressor {
+                encoder,
+                min_size,
+                stats,
+            },
+            decoder,
+        }
+    }
+}
+
+impl Extension for Compression {
+    fn bits(&self) -> RsvBits {
+        RsvBits {
+            rsv1: self.decoder.is_some(),
+            rsv2: false,
+            rsv3: false,
+        }
+    }
+}
+
+impl ExtensionEncoder for Compression {
+    type Error = DeflateExtensionError;
+
+    fn encode(
+        &mut self,
+        payload: &mut BytesMut,
+        header: &mut FrameHeader,
+    ) -> Result<(), Self::Error> {
+        self.compressor.encode(payload, header)
+    }
+}
+
+impl ExtensionDecoder for Compression {
+    type Error = DeflateExtensionError;
+
+    fn decode(
+        &mut self,
+        payload: &mut BytesMut,
+        header: &mut FrameHeader,
+    ) -> Result<(), Self::Error> {
+        self.decoder.decode(payload, header)
+    }
+}
+
+impl SplittableExtension for Compression {
+    type SplitEncoder = Compressor;
+    type SplitDecoder = Option<DeflateDecoder>;
+
+    fn split(self) -> (Self::SplitEncoder, Self::SplitDecoder) {
+        (self.compressor, self.decoder)
+    }
+}
+
+/// Sending half of [`Compression`]
+#[derive(Debug)]
+pub struct Compressor {
+    encoder: Option<DeflateEncoder>,
+    min_size: usize, // minimum size of the frames to compress
+    stats: Arc<CompressStats>,
+}
+
+impl ExtensionEncoder for Compressor {
+    type Error = DeflateExtensionError;
+
+    fn encode(
+        &mut self,
+        payload: &mut BytesMut,
+        header: &mut FrameHeader,
+    ) -> Result<(), Self::Error> {
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(());
+        };
+
+        // frames sent as they are leave RSV1 unset, which is allowed by the RFC
+        let raw = payload.len();
+        if raw >= self.min_size {
+            encoder.encode(payload, header)?;
+        }
+        self.stats.record(raw, payload.len());
+        Ok(())
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratchet_deflate::DeflateExtProvider;
+    use ratchet_ext::{ExtensionProvider, HeaderMap, HeaderValue, OpCode};
+
+    fn negotiate() -> Deflate {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "sec-websocket-extensions",
+            HeaderValue::from_static("permessage-deflate"),
+        );
+        let (deflate, _) = DeflateExtProvider::default()
+            .negotiate_server(&headers)
+            .unwrap()
+            .unwrap();
+        deflate
+    }
+
+    fn text_header() -> FrameHeader {
+        FrameHeader {
+            fin: true,
+            rsv1: false,
+            rsv2: false,
+            rsv3: false,
+            opcode: OpCode::Text,
+        }
+    }
+
+    #[test]
+    fn test_compression() {
+        let stats = Arc::new(CompressStats::default());
+        let mut ext = Compression::new(Some(negotiate()), 64, stats.clone());
+        assert!(ext.bits().rsv1);
+
+        // small frames are sent as they are
+        let mut header = text_header();
+        let mut payload = BytesMut::from(r#"{"action":"resync"}"#);
+        ext.encode(&mut payload, &mut header).unwrap();
+        assert!(!header.rsv1);
+        assert_eq!(&payload[..], br#"{"action":"resync"}"#);
+        assert_eq!(stats.ratio(), 1.0);
+
+        let text = "x".repeat(200);
+        let mut header = text_header();
+        let mut payload = BytesMut::from(text.as_str());
+        ext.encode(&mut payload, &mut header).unwrap();
+        assert!(header.rsv1);
+        assert!(payload.len() < text.len());
+        assert!(stats.ratio() > 1.0);
+
+        let mut peer = Compression::new(Some(negotiate()), 64, stats);
+        peer.decode(&mut payload, &mut header).unwrap();
+        assert_eq!(&payload[..], text.as_bytes());
+    }
+
+    #[test]
+    fn test_no_compression() {
+        let stats = Arc::new(CompressStats::default());
+        let mut ext = Compression::new(None, 64, stats.clone());
+        assert!(!ext.bits().rsv1);
+
+        let text = "x".repeat(200);
+        let mut header = text_header();
+        let mut payload = BytesMut::from(text.as_str());
+        ext.encode(&mut payload, &mut header).unwrap();
+        assert!(!header.rsv1);
+        assert_eq!(&payload[..], text.as_bytes());
+
+        // frames of the other websockets do not dilute the ratio
+        let mut ext = Compression::new(Some(negotiate()), 64, stats.clone());
+        let mut header = text_header();
+        let mut payload = BytesMut::from(text.as_str());
+        ext.encode(&mut payload, &mut header).unwrap();
+        assert_eq!(stats.ratio(), text.len() as f64 / payload.len() as f64);
+    }
+}
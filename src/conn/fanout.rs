@@ -0,0 +1,73 @@
+use super::message::Message;
+use serde::{Deserialize, Serialize};
+
+/// Events of the hub that are published to all nodes in the fan-out mode
+///
+/// Every node applies them to its own rooms and users, including the node
+/// that published them, so that they are seen in the same order everywhere.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum HubEvent {
+    /// Send the event to all users in the room
+    Broadcast {
+        room_id: i64,
+        event: Message,
+    },
+    /// Send the event to all clients of the users, and keep it for the
+    /// users of this node without a client by the id
+    Tell {
+        users: Vec<i64>,
+        id: String,
+        event: Message,
+    },
+    AddMembers {
+        room_id: i64,
+        users: Vec<i64>,
+    },
+    RemoveMembers {
+        room_id: i64,
+        users: Vec<i64>,
+    },
+    DeleteRoom {
+        room_id: i64,
+        users: Vec<i64>,
+    },
+}
+
+impl HubEvent {
+    /// Check whether the event changes the rooms of the users
+    pub fn is_membership(&self) -> bool {
+        matches!(
+            self,
+            Self::AddMembers { .. } | Self::RemoveMembers { .. } | Self::DeleteRoom { .. }
+        )
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hub_event() {
+        let event = HubEvent::Broadcast {
+            room_id: 1,
+            event: Message::from_text(r#"{"action":"typing"}"#.to_owned()).unwrap(),
+        };
+        let text = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            text,
+            r#"{"kind":"broadcast","room_id":1,"event":"{\"action\":\"typing\"}"}"#
+        );
+        assert_eq!(serde_json::from_str::<HubEvent>(&text).unwrap(), event);
+
+        let text = r#"{"kind":"add-members","room_id":2,"users":[3,4]}"#;
+        let event = HubEvent::AddMembers {
+            room_id: 2,
+            users: vec![3, 4],
+        };
+        assert_eq!(serde_json::from_str::<HubEvent>(text).unwrap(), event);
+    }
+}
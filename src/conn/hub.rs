@@ -1,69 +1,500 @@
-use super::{client::Client, state::HubState};
-use crate::{api::HubStatusResponse, core::Error};
-use axum::extract::ws::Message;
-use tokio::sync::RwLock;
+use super::message::Message;
+use super::{
+    client::Client,
+    fanout::HubEvent,
+    mailbox::{Mailbox, SlowClientPolicy},
+    state::HubState,
+    stats::{CompressStats, DropStats},
+    typing::TypingState,
+};
+use crate::{
+    api::{HubStatusResponse, ResumeResponse, ServerEvent, TypingResponse},
+    core::{
+        constant::{
+            CHAN_CAPACITY, NODE_HEARTBEAT_SECONDS, SHUTDOWN_TIMEOUT_SECONDS, TYPING_SWEEP_SECONDS,
+        },
+        Error,
+    },
+    store::Store,
+    Config,
+};
+use futures::StreamExt;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::{
+    sync::{watch, Mutex},
+    time::{self, Duration, Instant},
+};
+use uuid::Uuid;
 
-#[derive(Default)]
 pub struct Hub {
-    inner: RwLock<HubState>,
+    inner: HubState,
+    typing: Mutex<TypingState>,
+    store: Store,
+    node: Option<String>, // id of this node if the events are fanned out
+    policy: SlowClientPolicy,
+    stats: Arc<DropStats>,
+    compress: Arc<CompressStats>,
+    closed: AtomicBool,           // whether new events are refused
+    closing: watch::Sender<bool>, // tells the websockets to close
 }
 
 impl Hub {
-    pub async fn broadcast(&self, room_id: i64, msg: Message) -> Result<(), Error> {
-        let inner = self.inner.read().await;
-        inner.send(room_id, msg).await
+    /// Create the hub, which publishes the events to all nodes over Redis
+    /// in the fan-out mode, and keeps them in this process otherwise
+    ///
+    /// The events buffered to resume a session stay on the node of the
+    /// user, so a client resuming on another node has to initialize again.
+    pub fn new(store: Store, config: &Config) -> Self {
+        Self {
+            inner: HubState::default(),
+            typing: Mutex::default(),
+            store,
+            node: config.hub_fanout.then(|| Uuid::new_v4().to_string()),
+            policy: config.slow_client_policy,
+            stats: Arc::default(),
+            compress: Arc::default(),
+            closed: AtomicBool::new(false),
+            closing: watch::Sender::new(false),
+        }
+    }
+
+    /// Return the counters of the compressed frames
+    pub fn compress_stats(&self) -> Arc<CompressStats> {
+        self.compress.clone()
+    }
+
+    /// Check whether the hub is shutting down
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Subscribe the signal to close the websocket
+    pub fn on_closing(&self) -> watch::Receiver<bool> {
+        self.closing.subscribe()
+    }
+
+    /// Stop accepting events, deliver the pending ones and close the websockets
+    ///
+    /// Returns once all websockets are closed, or the shutdown timed out.
+    pub async fn shutdown(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+
+        let drain = async {
+            self.inner.drain().await;
+            self.closing.send_replace(true);
+            self.closing.closed().await;
+        };
+        let timeout = Duration::from_secs(SHUTDOWN_TIMEOUT_SECONDS);
+        if time::timeout(timeout, drain).await.is_err() {
+            tracing::warn!("hub shutdown timed out");
+        }
+
+        // other nodes stop counting the clients of this node
+        if let Some(node) = &self.node {
+            let users = self.inner.users().await;
+            if let Err(e) = self.store.remove_node(node, &users).await {
+                tracing::error!("failed to remove the presence of the node: {}", e);
+            }
+        }
+    }
+
+    /// Create the queue of events for a new client
+    pub fn mailbox(&self) -> Mailbox {
+        Mailbox::new(CHAN_CAPACITY, self.policy, self.stats.clone())
     }
 
-    pub async fn notify(&self, users: &Vec<i64>, msg: Message) -> Result<(), Error> {
-        let inner = self.inner.read().await;
+    pub async fn broadcast(&self, room_id: i64, event: Message) -> Result<(), Error> {
+        self.dispatch(HubEvent::Broadcast { room_id, event }).await
+    }
+
+    /// Send message to the users, and keep it for the offline ones
+    ///
+    /// Whether a user is online is decided in the same step as keeping the
+    /// event, so a user who leaves in between still gets it later. A user
+    /// without clients is offline even while the session can be resumed,
+    /// so the event is kept until the next initialize or resume.
+    pub async fn notify(&self, users: &[i64], msg: Message) -> Result<(), Error> {
+        if users.is_empty() {
+            return Ok(());
+        }
+
+        // in the fan-out mode the users without a live client on any node
+        // are kept here, and the nodes of the others keep those who leave
+        let id = Store::offline_event_id();
+        let users = match self.node {
+            Some(_) => {
+                self.store
+                    .keep_offline_event(users, &id, msg.text())
+                    .await?
+            }
+            None => users.to_vec(),
+        };
+        if users.is_empty() {
+            return Ok(());
+        }
+        self.dispatch(HubEvent::Tell {
+            users,
+            id,
+            event: msg,
+        })
+        .await
+    }
+
+    /// Send message to the user, and keep it if the user is offline
+    pub async fn tell(&self, user_id: i64, msg: Message) -> Result<(), Error> {
+        self.notify(&[user_id], msg).await
+    }
+
+    pub async fn status(&self) -> Result<HubStatusResponse, Error> {
+        self.inner.status(&self.stats, &self.compress).await
+    }
+
+    /// Check whether the user is in the room
+    ///
+    /// The rooms of a user are kept by the node that the user connects to,
+    /// and the membership changes of all nodes are applied there.
+    pub async fn is_user_in(&self, user_id: i64, room_id: i64) -> bool {
+        self.inner.is_user_in(user_id, room_id).await
+    }
+
+    /// Get the users with a live client
+    pub async fn online_users(&self, users: &[i64]) -> Result<Vec<i64>, Error> {
+        if self.node.is_some() {
+            return self.store.online_users(users).await;
+        }
+
+        let mut online = Vec::new();
         for &user_id in users {
-            if let Some(room_id) = inner.user_room(user_id) {
-                inner.send(room_id, msg.clone()).await?;
+            if self.inner.is_online(user_id).await {
+                online.push(user_id);
+            }
+        }
+        Ok(online)
+    }
+
+    pub async fn is_online(&self, user_id: i64) -> bool {
+        if self.node.is_some() {
+            return self.store.count_clients(user_id).await.is_ok_and(|n| n > 0);
+        }
+        self.inner.is_online(user_id).await
+    }
+
+    /// Record the user typing, and return whether the user just started,
+    /// or `None` if the refresh is ignored
+    ///
+    /// In the fan-out mode the typing is shared by all nodes, and the state
+    /// of this node only limits the refreshes.
+    pub async fn start_typing(&self, room_id: i64, user_id: i64) -> Result<Option<bool>, Error> {
+        let first = self
+            .typing
+            .lock()
+            .await
+            .start(room_id, user_id, Instant::now());
+        match (&self.node, first) {
+            (Some(_), Some(_)) => self.store.start_typing(room_id, user_id).await.map(Some),
+            _ => Ok(first),
+        }
+    }
+
+    pub async fn stop_typing(&self, room_id: i64, user_id: i64) -> Result<bool, Error> {
+        let typing = self.typing.lock().await.stop(room_id, user_id);
+        match self.node {
+            Some(_) => self.store.stop_typing(room_id, user_id).await,
+            None => Ok(typing),
+        }
+    }
+
+    /// Stop the typing that is not refreshed in time, until the hub is closed
+    pub async fn expire_typing(&self) {
+        let mut closing = self.on_closing();
+        let mut interval = time::interval(Duration::from_secs(TYPING_SWEEP_SECONDS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = closing.changed() => break,
+            }
+
+            let expired = self.typing.lock().await.sweep(Instant::now());
+            let expired = match self.node {
+                Some(_) => match self.store.sweep_typing().await {
+                    Ok(expired) => expired,
+                    Err(e) => {
+                        tracing::error!("failed to sweep typing: {}", e);
+                        continue;
+                    }
+                },
+                None => expired,
+            };
+
+            for (room_id, user_id) in expired {
+                let rsp = TypingResponse { room_id, user_id };
+                if let Ok(msg) = ServerEvent::TypingStop(rsp).to_msg() {
+                    let _ = self.broadcast(room_id, msg).await;
+                }
             }
         }
+    }
+
+    pub async fn connect(
+        &self,
+        client: &Client,
+        rooms: Vec<i64>,
+    ) -> Result<(bool, ResumeResponse), Error> {
+        let (first, rsp) = self.inner.register_client(client, rooms).await?;
+        let total = self.sync_presence(client.user_id()).await?;
+        Ok((first && total.is_none_or(|n| n == 1), rsp))
+    }
+
+    pub async fn resume(
+        &self,
+        client: &Client,
+        token: &str,
+        cursor: u64,
+    ) -> Result<(bool, ResumeResponse), Error> {
+        let (first, rsp) = self.inner.resume_client(client, token, cursor).await?;
+        let total = self.sync_presence(client.user_id()).await?;
+        Ok((first && total.is_none_or(|n| n == 1), rsp))
+    }
+
+    pub async fn disconnect(&self, client: &Client) -> Result<bool, Error> {
+        let last = self.inner.unregister_client(client).await?;
+        let total = self.sync_presence(client.user_id()).await?;
+        Ok(last && total.is_none_or(|n| n == 0))
+    }
+
+    pub async fn expire_user(&self, user_id: i64, grace: Duration) -> Result<(), Error> {
+        self.inner.expire_user(user_id, grace).await?;
+        self.sync_presence(user_id).await?;
         Ok(())
     }
 
-    pub async fn tell(&self, user_id: i64, msg: Message) -> Result<(), Error> {
-        let inner = self.inner.read().await;
-        if let Some(room_id) = inner.user_room(user_id) {
-            inner.send(room_id, msg).await?;
+    pub async fn add_members(&self, room_id: i64, users: &[i64]) -> Result<(), Error> {
+        let users = users.to_vec();
+        self.dispatch(HubEvent::AddMembers { room_id, users }).await
+    }
+
+    pub async fn remove_members(&self, room_id: i64, users: &[i64]) -> Result<(), Error> {
+        let users = users.to_vec();
+        self.dispatch(HubEvent::RemoveMembers { room_id, users })
+            .await
+    }
+
+    pub async fn delete_room(&self, room_id: i64, users: &[i64]) -> Result<(), Error> {
+        let users = users.to_vec();
+        self.dispatch(HubEvent::DeleteRoom { room_id, users }).await
+    }
+
+    /// Apply the events published by all nodes until the hub is closed
+    ///
+    /// It subscribes again if the connection to Redis is lost, and keeps
+    /// the node marked alive meanwhile.
+    pub async fn relay(&self) {
+        let Some(node) = &self.node else {
+            return;
+        };
+
+        let mut closing = self.on_closing();
+        let listen = async {
+            loop {
+                if let Err(e) = self.subscribe().await {
+                    tracing::error!("failed to relay hub events: {}", e);
+                }
+                time::sleep(Duration::from_secs(1)).await;
+            }
+        };
+        let heartbeat = async {
+            let mut interval = time::interval(Duration::from_secs(NODE_HEARTBEAT_SECONDS));
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.store.beat_node(node).await {
+                    tracing::error!("failed to mark the node alive: {}", e);
+                }
+            }
+        };
+
+        // the events are drained before the websockets are told to close
+        tokio::select! {
+            _ = listen => {}
+            _ = heartbeat => {}
+            _ = closing.changed() => {}
+        }
+    }
+
+    async fn subscribe(&self) -> Result<(), Error> {
+        let mut pubsub = self.store.subscribe_hub_events().await?;
+        let mut stream = pubsub.on_message();
+
+        while let Some(msg) = stream.next().await {
+            let payload: String = msg.get_payload()?;
+            let event = serde_json::from_str(&payload)?;
+            if let Err(e) = self.apply(event).await {
+                tracing::error!("failed to apply hub event: {}", e);
+            }
         }
         Ok(())
     }
 
-    pub async fn status(&self) -> Result<HubStatusResponse, Error> {
-        let inner = self.inner.read().await;
-        inner.status()
+    /// Publish the event to all nodes in the fan-out mode, or apply it here
+    async fn dispatch(&self, event: HubEvent) -> Result<(), Error> {
+        if self.is_closed() {
+            return Err(Error::ShuttingDown);
+        }
+
+        if self.node.is_some() {
+            // membership is changed here at once, so that the next request
+            // sees it, and again in the order of the events of all nodes,
+            // which is fine as applying a membership change is idempotent
+            if event.is_membership() {
+                self.apply(event.clone()).await?;
+            }
+            let payload = serde_json::to_string(&event)?;
+            self.store.publish_hub_event(&payload).await
+        } else {
+            self.apply(event).await
+        }
     }
 
-    pub async fn is_user_in(&self, user_id: i64, room_id: i64) -> bool {
-        let inner = self.inner.read().await;
-        inner.is_user_in(user_id, room_id)
+    /// Apply the event to the rooms and users of this node
+    async fn apply(&self, event: HubEvent) -> Result<(), Error> {
+        match event {
+            HubEvent::Broadcast { room_id, event } => self.send(room_id, event).await,
+            HubEvent::Tell { users, id, event } => {
+                let (id, text) = (id.as_str(), event.text());
+                let keep = |offline: Vec<i64>| async move {
+                    if let Err(e) = self.store.push_offline_events(&offline, id, text).await {
+                        tracing::error!("failed to keep the event for offline users: {}", e);
+                    }
+                };
+                // the users of other nodes are told there
+                let keep_absent = self.node.is_none();
+                self.inner.tell(&users, &event, keep_absent, keep).await;
+                Ok(())
+            }
+            HubEvent::AddMembers { room_id, users } => {
+                self.inner.add_members(room_id, &users).await
+            }
+            HubEvent::RemoveMembers { room_id, users } => {
+                self.inner.remove_members(room_id, &users).await
+            }
+            HubEvent::DeleteRoom { room_id, users } => {
+                self.inner.delete_room(room_id, &users).await
+            }
+        }
     }
 
-    pub async fn connect(&self, client: &Client, rooms: Vec<i64>) -> Result<(), Error> {
-        let mut inner = self.inner.write().await;
-        inner.register_client(client, rooms).await
+    /// Send message to the users of the room on this node
+    async fn send(&self, room_id: i64, msg: Message) -> Result<(), Error> {
+        self.inner.send(room_id, msg).await
     }
 
-    pub async fn disconnect(&self, client: &Client) -> Result<(), Error> {
-        let mut inner = self.inner.write().await;
-        inner.unregister_client(client).await
+    /// Share the number of clients of the user on this node with other nodes
+    ///
+    /// Returns the number of clients on all nodes, or `None` if the events
+    /// are not fanned out.
+    async fn sync_presence(&self, user_id: i64) -> Result<Option<usize>, Error> {
+        let Some(node) = &self.node else {
+            return Ok(None);
+        };
+
+        match self.inner.num_clients(user_id).await {
+            Some(clients) => self
+                .store
+                .set_presence(user_id, node, clients)
+                .await
+                .map(Some),
+            None => {
+                self.store.remove_presence(user_id, node).await?;
+                self.store.count_clients(user_id).await.map(Some)
+            }
+        }
     }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use tokio::task::JoinHandle;
 
-    pub async fn add_members(&self, room_id: i64, users: &Vec<i64>) -> Result<(), Error> {
-        let mut inner = self.inner.write().await;
-        inner.add_members(room_id, users).await
+    /// Start a node that fans out the events, and the task relaying them
+    async fn start_node() -> (Arc<Hub>, JoinHandle<()>) {
+        dotenvy::dotenv().ok();
+        let mut config = Config::from_env();
+        config.hub_fanout = true;
+
+        let hub = Arc::new(Hub::new(Store::new(&config).await, &config));
+        let node = hub.clone();
+        let relay = tokio::spawn(async move { node.relay().await });
+        (hub, relay)
     }
 
-    pub async fn remove_members(&self, room_id: i64, users: &Vec<i64>) -> Result<(), Error> {
-        let mut inner = self.inner.write().await;
-        inner.remove_members(room_id, users).await
+    /// Wait until the condition holds, or panic after a few seconds
+    async fn eventually<F, Fut>(cond: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        for _ in 0..50 {
+            if cond().await {
+                return;
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("condition not met in time");
     }
 
-    pub async fn delete_room(&self, room_id: i64, users: &Vec<i64>) -> Result<(), Error> {
-        let mut inner = self.inner.write().await;
-        inner.delete_room(room_id, users)
+    /// Runs two nodes against the database and redis of `.env`
+    ///
+    /// Run it with `cargo test two_nodes -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn two_nodes() {
+        let (a, _) = start_node().await;
+        let (b, relay) = start_node().await;
+        // let both nodes subscribe before publishing
+        time::sleep(Duration::from_millis(200)).await;
+
+        let user_id = rand::random::<u32>() as i64;
+        let room_id = rand::random::<u32>() as i64;
+        let client = Client::new(user_id, room_id, b.mailbox());
+        b.connect(&client, vec![room_id]).await.unwrap();
+        assert!(a.is_online(user_id).await);
+
+        // events published by a node reach the clients of the other
+        let event = r#"{"action":"typing"}"#.to_owned();
+        a.broadcast(room_id, Message::from_text(event.clone()).unwrap())
+            .await
+            .unwrap();
+        let msg = time::timeout(Duration::from_secs(5), client.recv())
+            .await
+            .unwrap();
+        assert!(msg.is_some_and(|msg| msg.text().contains("typing")));
+
+        // membership changes are seen by the next request on the same node
+        b.add_members(room_id + 1, &[user_id]).await.unwrap();
+        assert!(b.is_user_in(user_id, room_id + 1).await);
+
+        // and by the node of the user when made on another node
+        a.remove_members(room_id + 1, &[user_id]).await.unwrap();
+        eventually(|| async { !b.is_user_in(user_id, room_id + 1).await }).await;
+
+        // typing is shared, so it can be stopped from another node
+        assert_eq!(a.start_typing(room_id, user_id).await.unwrap(), Some(true));
+        assert_eq!(b.start_typing(room_id, user_id).await.unwrap(), Some(false));
+        assert!(b.stop_typing(room_id, user_id).await.unwrap());
+        assert!(!a.stop_typing(room_id, user_id).await.unwrap());
+
+        // the clients of a node are not counted once it shuts down
+        b.shutdown().await;
+        assert!(!a.is_online(user_id).await);
+        time::timeout(Duration::from_secs(1), relay)
+            .await
+            .unwrap()
+            .unwrap();
     }
 }
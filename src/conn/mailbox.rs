@@ -0,0 +1,226 @@
+use super::message::Message;
+use super::stats::DropStats;
+use crate::{api::ServerEvent, core::Error};
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+use tokio::sync::Notify;
+
+/// What to do with a client that does not keep up with its events
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlowClientPolicy {
+    /// Drop the oldest queued event to make room for the new one
+    DropOldest,
+    /// Close the connection of the client
+    Disconnect,
+    /// Drop the queued events and ask the client to resume from its cursor
+    Resync,
+}
+
+impl FromStr for SlowClientPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" => Ok(Self::DropOldest),
+            "disconnect" => Ok(Self::Disconnect),
+            "resync" => Ok(Self::Resync),
+            _ => Err(format!("unknown slow client policy: {}", s)),
+        }
+    }
+}
+
+/// Queue of the events waiting to be written to the websocket of a client
+///
+/// Events of the rooms are put without waiting, and the policy is applied
+/// once the queue is full, so that a slow client never blocks the others.
+pub struct Mailbox {
+    queue: Mutex<Queue>,
+    ready: Notify,
+    space: Notify,
+    capacity: usize,
+    policy: SlowClientPolicy,
+    stats: Arc<DropStats>,
+}
+
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<Message>,
+    slow: bool,   // whether the client has ever been too slow
+    resync: bool, // whether the events are dropped until the client resyncs
+    closed: bool,
+}
+
+impl Mailbox {
+    pub fn new(capacity: usize, policy: SlowClientPolicy, stats: Arc<DropStats>) -> Self {
+        Self {
+            queue: Mutex::default(),
+            ready: Notify::new(),
+            space: Notify::new(),
+            capacity,
+            policy,
+            stats,
+        }
+    }
+
+    /// Put the reply to the client, waiting until there is room for it
+    pub async fn send(&self, msg: Message) -> Result<(), Error> {
+        loop {
+            {
+                let mut queue = self.queue.lock().unwrap();
+                if queue.closed {
+                    return Err(Error::SendMessage);
+                }
+                if queue.events.len() < self.capacity {
+                    queue.events.push_back(msg);
+                    self.ready.notify_one();
+                    return Ok(());
+                }
+            }
+            self.space.notified().await;
+        }
+    }
+
+    /// Put the event without waiting, and apply the policy if the queue is full
+    pub fn push(&self, msg: Message) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return;
+        }
+        if queue.resync {
+            self.stats.drop_events(1);
+            return;
+        }
+
+        if queue.events.len() >= self.capacity {
+            if !queue.slow {
+                queue.slow = true;
+                self.stats.add_slow_client();
+            }
+
+            match self.policy {
+                SlowClientPolicy::DropOldest => {
+                    queue.events.pop_front();
+                    self.stats.drop_events(1);
+                }
+                SlowClientPolicy::Disconnect => {
+                    self.stats.drop_events(queue.events.len() + 1);
+                    queue.events.clear();
+                    queue.closed = true;
+                    self.ready.notify_one();
+                    self.space.notify_waiters();
+                    return;
+                }
+                SlowClientPolicy::Resync => {
+                    self.stats.drop_events(queue.events.len() + 1);
+                    queue.events.clear();
+                    if let Ok(event) = ServerEvent::Resync.to_msg() {
+                        queue.events.push_back(event);
+                    }
+                    queue.resync = true;
+                    self.ready.notify_one();
+                    return;
+                }
+            }
+        }
+
+        queue.events.push_back(msg);
+        self.ready.notify_one();
+    }
+
+    /// Put the replayed events regardless of the capacity
+    pub fn extend(&self, msgs: Vec<Message>) {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.closed {
+            queue.events.extend(msgs);
+            self.ready.notify_one();
+        }
+    }
+
+    /// Take the next event, or `None` once the client is disconnected
+    pub async fn recv(&self) -> Option<Message> {
+        loop {
+            match self.poll() {
+                Poll::Ready(msg) => return msg,
+                Poll::Pending => self.ready.notified().await,
+            }
+        }
+    }
+
+    /// Take the next event without waiting
+    pub fn try_recv(&self) -> Option<Message> {
+        match self.poll() {
+            Poll::Ready(msg) => msg,
+            Poll::Pending => None,
+        }
+    }
+
+    /// Take the next event if there is one
+    fn poll(&self) -> Poll<Option<Message>> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return Poll::Ready(None);
+        }
+
+        let Some(msg) = queue.events.pop_front() else {
+            return Poll::Pending;
+        };
+        // the resync event stays in the front until it is taken
+        queue.resync = false;
+        self.space.notify_one();
+        Poll::Ready(Some(msg))
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(policy: SlowClientPolicy) -> (Mailbox, Arc<DropStats>) {
+        let stats = Arc::new(DropStats::default());
+        let mailbox = Mailbox::new(2, policy, stats.clone());
+        for i in 0..3 {
+            mailbox.push(Message::from_text(i.to_string()).unwrap());
+        }
+        (mailbox, stats)
+    }
+
+    fn text(msg: Poll<Option<Message>>) -> String {
+        match msg {
+            Poll::Ready(Some(msg)) => msg.text().to_owned(),
+            _ => panic!("no message"),
+        }
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let (mailbox, stats) = fill(SlowClientPolicy::DropOldest);
+        assert_eq!(text(mailbox.poll()), "1");
+        assert_eq!(text(mailbox.poll()), "2");
+        assert!(mailbox.poll().is_pending());
+        assert_eq!(stats.load(), (1, 1));
+    }
+
+    #[test]
+    fn disconnect() {
+        let (mailbox, stats) = fill(SlowClientPolicy::Disconnect);
+        assert_eq!(mailbox.poll(), Poll::Ready(None));
+        assert_eq!(stats.load(), (3, 1));
+    }
+
+    #[test]
+    fn resync() {
+        let (mailbox, stats) = fill(SlowClientPolicy::Resync);
+        mailbox.push(Message::from_text("3".to_owned()).unwrap());
+        assert_eq!(text(mailbox.poll()), r#"{"action":"resync"}"#);
+
+        mailbox.push(Message::from_text("4".to_owned()).unwrap());
+        assert_eq!(text(mailbox.poll()), "4");
+        assert_eq!(stats.load(), (4, 1));
+    }
+}
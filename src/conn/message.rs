@@ -0,0 +1,146 @@
+use crate::core::Error;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{borrow::Cow, sync::Arc};
+
+/// A server event on its way from the hub to the websockets
+///
+/// The event is encoded once for every codec and shared by all its
+/// receivers. The cursor of the user is kept aside, and only spliced into
+/// the envelope when the event is written to a client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    encoded: Arc<Encoded>,
+    cursor: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Encoded {
+    text: String,    // JSON
+    binary: Vec<u8>, // MessagePack
+}
+
+impl Message {
+    /// Encode the server event for both codecs
+    pub fn encode<T: Serialize>(event: &T) -> Result<Self, Error> {
+        let text = serde_json::to_string(event)?;
+        let binary = rmp_serde::to_vec_named(event)?;
+        Ok(Self::with_encoded(text, binary))
+    }
+
+    /// Wrap the JSON text of a server event, e.g. one from another node
+    pub fn from_text(text: String) -> Result<Self, Error> {
+        let value: serde_json::Value = serde_json::from_str(&text)?;
+        let binary = rmp_serde::to_vec_named(&value)?;
+        Ok(Self::with_encoded(text, binary))
+    }
+
+    fn with_encoded(text: String, binary: Vec<u8>) -> Self {
+        Self {
+            encoded: Arc::new(Encoded { text, binary }),
+            cursor: None,
+        }
+    }
+
+    /// Return the JSON text of the event without the cursor
+    pub fn text(&self) -> &str {
+        &self.encoded.text
+    }
+
+    /// Number the event for a user
+    pub fn with_cursor(&self, cursor: u64) -> Self {
+        Self {
+            encoded: self.encoded.clone(),
+            cursor: Some(cursor),
+        }
+    }
+
+    /// Return the JSON text of the event with the cursor in the envelope
+    pub fn to_text(&self) -> Cow<'_, str> {
+        let text = &self.encoded.text;
+        let (Some(cursor), Some(head)) = (self.cursor, text.strip_suffix('}')) else {
+            return Cow::Borrowed(text);
+        };
+        let sep = if head.ends_with('{') { "" } else { "," };
+        Cow::Owned(format!(r#"{}{}"cursor":{}}}"#, head, sep, cursor))
+    }
+
+    /// Return the MessagePack of the event with the cursor in the envelope
+    pub fn to_binary(&self) -> Cow<'_, [u8]> {
+        let data = &self.encoded.binary;
+        let Some(cursor) = self.cursor else {
+            return Cow::Borrowed(data);
+        };
+        let mut fields = &data[..];
+        let Ok(len) = rmp::decode::read_map_len(&mut fields) else {
+            return Cow::Borrowed(data);
+        };
+
+        // the map header grows by one entry, and the fields stay as they are
+        let mut buf = Vec::with_capacity(data.len() + 16);
+        let _ = rmp::encode::write_map_len(&mut buf, len + 1);
+        buf.extend_from_slice(fields);
+        let _ = rmp::encode::write_str(&mut buf, "cursor");
+        let _ = rmp::encode::write_uint(&mut buf, cursor);
+        Cow::Owned(buf)
+    }
+}
+
+/// Published to the other nodes as the JSON text, without the cursor
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.text())
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Self::from_text(text).map_err(de::Error::custom)
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn splice_cursor() {
+        let msg = Message::from_text(r#"{"action":"b","data":{"id":1}}"#.to_owned()).unwrap();
+        assert_eq!(msg.to_text(), msg.text());
+
+        let msg = msg.with_cursor(2);
+        assert_eq!(
+            msg.to_text(),
+            r#"{"action":"b","data":{"id":1},"cursor":2}"#
+        );
+        assert_eq!(msg.text(), r#"{"action":"b","data":{"id":1}}"#);
+
+        let msg = Message::from_text("{}".to_owned()).unwrap().with_cursor(3);
+        assert_eq!(msg.to_text(), r#"{"cursor":3}"#);
+    }
+
+    #[test]
+    fn splice_binary_cursor() {
+        let event = json!({ "action": "b", "data": { "id": 1 } });
+        let msg = Message::encode(&event).unwrap();
+        let value = rmp_serde::from_slice::<serde_json::Value>(&msg.to_binary()).unwrap();
+        assert_eq!(value, event);
+
+        let msg = msg.with_cursor(2);
+        let value = rmp_serde::from_slice::<serde_json::Value>(&msg.to_binary()).unwrap();
+        assert_eq!(
+            value,
+            json!({ "action": "b", "data": { "id": 1 }, "cursor": 2 })
+        );
+
+        // a map of 15 entries needs a longer header with the cursor
+        let event = serde_json::Value::Object((0..15).map(|i| (i.to_string(), json!(i))).collect());
+        let msg = Message::encode(&event).unwrap().with_cursor(u64::MAX);
+        let value = rmp_serde::from_slice::<serde_json::Value>(&msg.to_binary()).unwrap();
+        assert_eq!(value.as_object().unwrap().len(), 16);
+        assert_eq!(value["cursor"], json!(u64::MAX));
+    }
+}
@@ -1,10 +1,28 @@
 //! Management of connections for Chat room
 
+mod deflate;
+mod fanout;
+mod mailbox;
+mod message;
+mod outbox;
 mod room;
+mod shard;
 mod state;
+mod stats;
+mod typing;
+mod user;
 
 mod client;
 pub use client::Client;
 
 mod hub;
+pub use deflate::Compression;
 pub use hub::Hub;
+pub use mailbox::SlowClientPolicy;
+pub use message::Message;
+
+// used by the benchmarks
+#[cfg(feature = "bench")]
+pub use {
+    mailbox::Mailbox, room::RoomAction, state::HubState, stats::CompressStats, stats::DropStats,
+};
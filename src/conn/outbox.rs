@@ -0,0 +1,101 @@
+use super::message::Message;
+use std::collections::VecDeque;
+
+/// Recent events sent to a user, numbered by a per-user cursor
+///
+/// The cursor of the first event is 1, and a client that has seen the
+/// events up to a cursor can get the rest as long as they are kept.
+///
+/// The outbox lives in the memory of the node that the user connects to,
+/// so a client resuming on another node gets `ResumeExpired` and has to
+/// initialize again.
+pub struct Outbox {
+    cursor: u64,
+    capacity: usize,
+    events: VecDeque<(u64, Message)>,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cursor: 0,
+            capacity,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Return the cursor of the latest event
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Number the event and keep it, dropping the oldest one if full
+    pub fn push(&mut self, msg: Message) -> Message {
+        self.cursor += 1;
+        let msg = msg.with_cursor(self.cursor);
+
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back((self.cursor, msg.clone()));
+        msg
+    }
+
+    /// Get the events after the cursor, or `None` if some have been dropped
+    pub fn since(&self, cursor: u64) -> Option<Vec<Message>> {
+        let first = self.events.front().map_or(self.cursor + 1, |(c, _)| *c);
+        if cursor + 1 < first || cursor > self.cursor {
+            return None;
+        }
+
+        let events = self
+            .events
+            .iter()
+            .filter(|(c, _)| *c > cursor)
+            .map(|(_, msg)| msg.clone())
+            .collect();
+        Some(events)
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(msg: &Message) -> String {
+        msg.to_text().into_owned()
+    }
+
+    #[test]
+    fn replay_events() {
+        let mut outbox = Outbox::new(2);
+        assert_eq!(outbox.since(0).unwrap().len(), 0);
+
+        let msg = outbox.push(Message::from_text(r#"{"action":"a"}"#.to_owned()).unwrap());
+        assert_eq!(text(&msg), r#"{"action":"a","cursor":1}"#);
+        outbox.push(Message::from_text(r#"{"action":"b","data":{"id":1}}"#.to_owned()).unwrap());
+        outbox.push(Message::from_text(r#"{"action":"c"}"#.to_owned()).unwrap());
+        assert_eq!(outbox.cursor(), 3);
+
+        let events = outbox.since(1).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            text(&events[0]),
+            r#"{"action":"b","data":{"id":1},"cursor":2}"#
+        );
+        assert_eq!(outbox.since(3).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn dropped_events() {
+        let mut outbox = Outbox::new(2);
+        for _ in 0..3 {
+            outbox.push(Message::from_text(r#"{"action":"resync"}"#.to_owned()).unwrap());
+        }
+
+        assert!(outbox.since(0).is_none());
+        assert!(outbox.since(4).is_none());
+    }
+}
@@ -1,47 +1,40 @@
-use super::Client;
-use axum::extract::ws::Message;
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    fmt,
-};
-use tokio::sync::mpsc;
-use uuid::Uuid;
+use super::message::Message;
+use super::user::UserAction;
+use std::{collections::HashMap, fmt};
+use tokio::sync::{mpsc, oneshot};
 
 pub enum RoomAction {
-    /// Send message to all clients in this room
+    /// Send message to all users in this room
     Send(Message),
-    /// A new client join the room
-    Join(Client),
-    /// A client left the room
-    Left(i64, Uuid),
     /// Add a user in the room
-    Add(i64, HashMap<Uuid, mpsc::Sender<Message>>),
+    Add(i64, mpsc::Sender<UserAction>),
     /// Remove a user from the room
     Del(i64),
+    /// Reply once the actions queued before are done
+    Flush(oneshot::Sender<()>),
 }
 
+/// Fans out the events of a room to the user tasks
+///
+/// User tasks put the events to their clients without waiting, so the
+/// room is never held up by a slow client.
 pub struct ChatRoom {
     rx: mpsc::Receiver<RoomAction>,
-    txs: HashMap<i64, HashMap<Uuid, mpsc::Sender<Message>>>,
+    txs: HashMap<i64, mpsc::Sender<UserAction>>,
 }
 
 impl fmt::Display for ChatRoom {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let num_users = self.txs.len();
-        let mut num_clients = 0_usize;
-        for clients in self.txs.values() {
-            num_clients += clients.len();
-        }
-        write!(f, "users: {}, clients: {}", num_users, num_clients)
+        write!(f, "users: {}", self.txs.len())
     }
 }
 
 impl ChatRoom {
-    pub fn new(rx: mpsc::Receiver<RoomAction>) -> Self {
-        Self {
-            rx,
-            txs: HashMap::default(),
-        }
+    pub fn new(
+        rx: mpsc::Receiver<RoomAction>,
+        txs: HashMap<i64, mpsc::Sender<UserAction>>,
+    ) -> Self {
+        Self { rx, txs }
     }
 
     pub async fn serve(&mut self) {
@@ -53,37 +46,19 @@ impl ChatRoom {
     async fn process(&mut self, action: RoomAction) {
         match action {
             RoomAction::Send(msg) => {
-                for senders in self.txs.values() {
-                    for sender in senders.values() {
-                        let _ = sender.send(msg.clone()).await;
-                    }
-                }
-            }
-            RoomAction::Join(client) => {
-                match self.txs.entry(client.user_id()) {
-                    Entry::Occupied(mut o) => {
-                        let senders = o.get_mut();
-                        senders.insert(client.id(), client.tx());
-                    }
-                    Entry::Vacant(v) => {
-                        let mut senders = HashMap::new();
-                        senders.insert(client.id(), client.tx());
-                        v.insert(senders);
-                    }
-                };
-            }
-            RoomAction::Left(id, uid) => {
-                if let Entry::Occupied(mut o) = self.txs.entry(id) {
-                    let senders = o.get_mut();
-                    senders.remove(&uid);
+                for sender in self.txs.values() {
+                    let _ = sender.send(UserAction::Send(msg.clone())).await;
                 }
             }
-            RoomAction::Add(id, senders) => {
-                self.txs.insert(id, senders);
+            RoomAction::Add(id, sender) => {
+                self.txs.insert(id, sender);
             }
             RoomAction::Del(id) => {
                 self.txs.remove(&id);
             }
+            RoomAction::Flush(reply) => {
+                let _ = reply.send(());
+            }
         }
     }
 }
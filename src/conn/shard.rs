@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A map split into shards by id, each with its own lock
+///
+/// Operations on the ids in different shards never wait for each other.
+pub struct Shards<T> {
+    shards: Vec<RwLock<HashMap<i64, T>>>,
+}
+
+impl<T> Shards<T> {
+    pub fn new(n: usize) -> Self {
+        let shards = (0..n).map(|_| RwLock::default()).collect();
+        Self { shards }
+    }
+
+    /// Lock the shard of the id for reading
+    pub async fn read(&self, id: i64) -> RwLockReadGuard<'_, HashMap<i64, T>> {
+        self.shards[self.index(id)].read().await
+    }
+
+    /// Lock the shard of the id for writing
+    pub async fn write(&self, id: i64) -> RwLockWriteGuard<'_, HashMap<i64, T>> {
+        self.shards[self.index(id)].write().await
+    }
+
+    /// Lock the shards of the ids for reading, each once and in order
+    ///
+    /// Taking the shards in order keeps two callers from waiting for each
+    /// other, as long as no one else holds two shards at once.
+    pub async fn read_many(&self, ids: &[i64]) -> ReadGuards<'_, T> {
+        let indexes: BTreeSet<usize> = ids.iter().map(|&id| self.index(id)).collect();
+        let mut guards = BTreeMap::new();
+        for index in indexes {
+            guards.insert(index, self.shards[index].read().await);
+        }
+        ReadGuards {
+            shards: self,
+            guards,
+        }
+    }
+
+    /// Return all shards to be locked one by one
+    pub fn iter(&self) -> impl Iterator<Item = &RwLock<HashMap<i64, T>>> {
+        self.shards.iter()
+    }
+
+    fn index(&self, id: i64) -> usize {
+        id.rem_euclid(self.shards.len() as i64) as usize
+    }
+}
+
+/// Read locks on the shards of some ids
+pub struct ReadGuards<'a, T> {
+    shards: &'a Shards<T>,
+    guards: BTreeMap<usize, RwLockReadGuard<'a, HashMap<i64, T>>>,
+}
+
+impl<T> ReadGuards<'_, T> {
+    /// Get the value of the id, which must be one of the locked ids
+    pub fn get(&self, id: i64) -> Option<&T> {
+        self.guards[&self.shards.index(id)].get(&id)
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shard_index() {
+        let shards: Shards<()> = Shards::new(4);
+        assert_eq!(shards.index(0), 0);
+        assert_eq!(shards.index(6), 2);
+        assert_eq!(shards.index(-1), 3);
+    }
+}
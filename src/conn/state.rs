@@ -1,181 +1,651 @@
 use super::client::Client;
+use super::message::Message;
 use super::room::{ChatRoom, RoomAction};
-use crate::api::HubStatusResponse;
-use crate::core::{constant::CHAN_CAPACITY, Error};
-use axum::extract::ws::Message;
+use super::shard::Shards;
+use super::stats::{CompressStats, DropStats};
+use super::user::{ChatUser, UserAction};
+use crate::api::{HubStatusResponse, ResumeResponse};
+use crate::core::{
+    constant::{CHAN_CAPACITY, MAX_BUFFERED_EVENT, NUM_HUB_SHARDS},
+    Error,
+};
 use std::collections::{hash_map::Entry, HashMap, HashSet};
-use tokio::{sync::mpsc, task::JoinHandle};
+use std::future::Future;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::{Duration, Instant},
+};
 use uuid::Uuid;
 
-#[derive(Default)]
+/// Users and rooms served by this node
+///
+/// Both maps are sharded by id so that operations on different users and
+/// rooms run at the same time. To avoid deadlocks a user shard may be held
+/// while locking a room shard, but never the other way around, and no two
+/// shards of the same map are held at once, except by `tell`, which reads
+/// several user shards in order.
+///
+/// The user shard also stays locked while the room tasks are told that the
+/// user joins or leaves, so that these actions reach each room in the order
+/// they were made. Such a send only waits while the queue of the room is
+/// full, and room tasks never wait on the clients, so the wait is short.
 pub struct HubState {
-    users: HashMap<i64, UserState>,
-    rooms: HashMap<i64, RoomState>,
+    users: Shards<UserState>,
+    rooms: Shards<RoomState>,
+}
+
+impl Default for HubState {
+    fn default() -> Self {
+        Self {
+            users: Shards::new(NUM_HUB_SHARDS),
+            rooms: Shards::new(NUM_HUB_SHARDS),
+        }
+    }
 }
 
 impl HubState {
-    /// Send message to all clients in the room
+    /// Return channel of the room task if the room has a live client
+    pub async fn room_chan(&self, room_id: i64) -> Option<mpsc::Sender<RoomAction>> {
+        let rooms = self.rooms.read(room_id).await;
+        rooms
+            .get(&room_id)
+            .and_then(|rs| rs.task.as_ref())
+            .map(|rt| rt.tx.clone())
+    }
+
+    /// Send message to all users of the room on this node
+    ///
+    /// A room without a live client has no task, so the message is put to
+    /// the user tasks directly, to be replayed when the users resume. The
+    /// room shard stays locked meanwhile to keep the messages in order.
     pub async fn send(&self, room_id: i64, msg: Message) -> Result<(), Error> {
-        if let Some(room) = self.rooms.get(&room_id) {
-            room.tx.send(RoomAction::Send(msg)).await?
+        if let Some(tx) = self.room_chan(room_id).await {
+            tx.send(RoomAction::Send(msg)).await?;
+            return Ok(());
+        }
+
+        let mut rooms = self.rooms.write(room_id).await;
+        let Some(rs) = rooms.get_mut(&room_id) else {
+            return Ok(());
+        };
+        if let Some(rt) = &rs.task {
+            rt.tx.send(RoomAction::Send(msg)).await?;
+            return Ok(());
+        }
+
+        // the messages queued in the stopped task go first
+        if let Some(handle) = rs.stopping.take() {
+            let _ = handle.await;
+        }
+        for tx in rs.users.values() {
+            let _ = tx.send(UserAction::Send(msg.clone())).await;
         }
         Ok(())
     }
 
-    pub fn status(&self) -> Result<HubStatusResponse, Error> {
-        let num_users = self.users.len();
-        let num_rooms = self.rooms.len();
-
+    pub async fn status(
+        &self,
+        stats: &DropStats,
+        compress: &CompressStats,
+    ) -> Result<HubStatusResponse, Error> {
+        let mut num_users = 0;
         let mut num_clients = 0;
-        for us in self.users.values() {
-            num_clients += us.txs.len();
+        for shard in self.users.iter() {
+            let users = shard.read().await;
+            num_users += users.len();
+            num_clients += users.values().map(|us| us.clients.len()).sum::<usize>();
         }
 
+        // rooms are idle if their users are all waiting to resume
+        let mut num_rooms = 0;
+        let mut num_idle_rooms = 0;
+        for shard in self.rooms.iter() {
+            let rooms = shard.read().await;
+            num_rooms += rooms.len();
+            num_idle_rooms += rooms.values().filter(|rs| rs.task.is_none()).count();
+        }
+
+        let (num_dropped_events, num_slow_clients) = stats.load();
         let rsp = HubStatusResponse {
             num_users,
             num_clients,
             num_rooms,
+            num_live_rooms: num_rooms - num_idle_rooms,
+            num_idle_rooms,
+            num_dropped_events,
+            num_slow_clients,
+            compression_ratio: compress.ratio(),
         };
         Ok(rsp)
     }
 
-    pub async fn register_client(&mut self, client: &Client, rooms: Vec<i64>) -> Result<(), Error> {
-        for room_id in &rooms {
-            let tx = self.get_room_chan(*room_id);
-            tx.send(RoomAction::Join(client.clone())).await?;
-        }
+    /// Register the client and return whether it is the first client of the user
+    ///
+    /// The user joins the rooms if the user has no buffered events.
+    pub async fn register_client(
+        &self,
+        client: &Client,
+        rooms: Vec<i64>,
+    ) -> Result<(bool, ResumeResponse), Error> {
+        let user_id = client.user_id();
+        let mut users = self.users.write(user_id).await;
 
-        match self.users.entry(client.user_id()) {
-            Entry::Occupied(mut o) => {
-                let us = o.get_mut();
-                us.txs.insert(client.id(), client.tx());
-            }
+        let us = match users.entry(user_id) {
+            Entry::Occupied(o) => o.into_mut(),
             Entry::Vacant(v) => {
-                let us = UserState::new(client, rooms);
-                v.insert(us);
+                let tx = Self::create_user();
+                for room_id in &rooms {
+                    self.join_room(*room_id, user_id, tx.clone(), false).await?;
+                }
+                v.insert(UserState::new(tx, rooms))
             }
         };
-        Ok(())
+
+        let (first, rsp) = us.attach_client(client, None).await?;
+        if first {
+            self.set_live(&us.rooms, user_id, true).await;
+        }
+        Ok((first, rsp))
     }
 
-    pub async fn unregister_client(&mut self, client: &Client) -> Result<(), Error> {
-        if let Some(us) = self.users.get_mut(&client.user_id()) {
-            for room_id in &us.rooms {
-                if let Some(room) = self.rooms.get(room_id) {
-                    room.tx
-                        .send(RoomAction::Left(client.user_id(), client.id()))
-                        .await?;
+    /// Register the client and replay the events it missed after the cursor
+    pub async fn resume_client(
+        &self,
+        client: &Client,
+        token: &str,
+        cursor: u64,
+    ) -> Result<(bool, ResumeResponse), Error> {
+        let mut users = self.users.write(client.user_id()).await;
+        let us = match users.get_mut(&client.user_id()) {
+            Some(us) if us.token == token => us,
+            _ => return Err(Error::ResumeExpired),
+        };
+
+        let (first, rsp) = us.attach_client(client, Some(cursor)).await?;
+        if first {
+            self.set_live(&us.rooms, client.user_id(), true).await;
+        }
+        Ok((first, rsp))
+    }
+
+    /// Unregister the client and return whether it was the last client of the user
+    ///
+    /// The user stays in the rooms so that the events are still buffered,
+    /// but the rooms left without a live client stop their tasks.
+    pub async fn unregister_client(&self, client: &Client) -> Result<bool, Error> {
+        let mut users = self.users.write(client.user_id()).await;
+        if let Some(us) = users.get_mut(&client.user_id()) {
+            if us.clients.remove(&client.id()) {
+                us.tx.send(UserAction::Detach(client.id())).await?;
+
+                if us.clients.is_empty() {
+                    us.idle_since = Some(Instant::now());
+                    self.set_live(&us.rooms, client.user_id(), false).await;
+                    return Ok(true);
                 }
             }
+        }
+        Ok(false)
+    }
 
-            us.txs.remove(&client.id());
-            if us.txs.is_empty() {
-                self.users.remove(&client.user_id());
+    /// Remove the user from the rooms if no client comes back in the grace period
+    pub async fn expire_user(&self, user_id: i64, grace: Duration) -> Result<(), Error> {
+        let mut users = self.users.write(user_id).await;
+        let expired = match users.get(&user_id) {
+            Some(us) => us.idle_since.is_some_and(|t| t.elapsed() >= grace),
+            None => false,
+        };
+
+        if expired {
+            if let Some(us) = users.remove(&user_id) {
+                for room_id in &us.rooms {
+                    self.leave_room(*room_id, user_id).await?;
+                }
             }
         }
         Ok(())
     }
 
-    pub async fn add_members(&mut self, room_id: i64, users: &Vec<i64>) -> Result<(), Error> {
-        let tx = self.get_room_chan(room_id);
-
-        for user_id in users {
-            if let Some(us) = self.users.get_mut(user_id) {
-                tx.send(RoomAction::Add(*user_id, us.txs.clone())).await?;
+    pub async fn add_members(&self, room_id: i64, users: &[i64]) -> Result<(), Error> {
+        for &user_id in users {
+            let mut shard = self.users.write(user_id).await;
+            if let Some(us) = shard.get_mut(&user_id) {
                 us.rooms.insert(room_id);
+                let live = !us.clients.is_empty();
+                self.join_room(room_id, user_id, us.tx.clone(), live)
+                    .await?;
             }
         }
         Ok(())
     }
 
-    pub async fn remove_members(&mut self, room_id: i64, users: &Vec<i64>) -> Result<(), Error> {
-        if let Some(rs) = self.rooms.get(&room_id) {
-            for user_id in users {
-                if let Some(us) = self.users.get_mut(user_id) {
-                    rs.tx.send(RoomAction::Del(*user_id)).await?;
-                    us.rooms.remove(&room_id);
-                }
+    pub async fn remove_members(&self, room_id: i64, users: &[i64]) -> Result<(), Error> {
+        for &user_id in users {
+            let mut shard = self.users.write(user_id).await;
+            if let Some(us) = shard.get_mut(&user_id) {
+                us.rooms.remove(&room_id);
+                self.leave_room(room_id, user_id).await?;
             }
         }
         Ok(())
     }
 
-    pub fn delete_room(&mut self, room_id: i64, users: &Vec<i64>) -> Result<(), Error> {
-        if let Some(rs) = self.rooms.get(&room_id) {
-            for user_id in users {
-                if let Some(us) = self.users.get_mut(user_id) {
-                    us.rooms.remove(&room_id);
-                }
+    pub async fn delete_room(&self, room_id: i64, users: &[i64]) -> Result<(), Error> {
+        for &user_id in users {
+            let mut shard = self.users.write(user_id).await;
+            if let Some(us) = shard.get_mut(&user_id) {
+                us.rooms.remove(&room_id);
+            }
+        }
+
+        let mut rooms = self.rooms.write(room_id).await;
+        if let Some(rs) = rooms.remove(&room_id) {
+            if let Some(rt) = rs.task {
+                rt.handle.abort();
+            }
+            if let Some(handle) = rs.stopping {
+                handle.abort();
             }
-            rs.task.abort();
-            self.rooms.remove(&room_id);
         }
         Ok(())
     }
 
-    pub fn is_user_in(&self, user_id: i64, room_id: i64) -> bool {
-        if let Some(us) = self.users.get(&user_id) {
-            us.rooms.contains(&room_id)
-        } else {
-            false
+    /// Send the message to the users with a live client, and pass the others to `keep`
+    ///
+    /// The shards of the users stay locked until `keep` is done, so that no
+    /// client of them connects or leaves in between, and the message is
+    /// either delivered or kept. The users not kept by this node are only
+    /// passed if `keep_absent` is set.
+    pub async fn tell<F, Fut>(&self, users: &[i64], msg: &Message, keep_absent: bool, keep: F)
+    where
+        F: FnOnce(Vec<i64>) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let shards = self.users.read_many(users).await;
+        let mut offline = Vec::new();
+        for &user_id in users {
+            let done = match shards.get(user_id) {
+                // the user task is gone if the send fails
+                Some(us) if !us.clients.is_empty() => {
+                    let action = UserAction::Send(msg.clone());
+                    us.tx.send(action).await.is_ok()
+                }
+                Some(_) => false,
+                None => !keep_absent,
+            };
+            if !done {
+                offline.push(user_id);
+            }
+        }
+
+        if !offline.is_empty() {
+            keep(offline).await;
         }
     }
 
-    pub fn user_room(&self, user_id: i64) -> Option<i64> {
-        self.users.get(&user_id).map(|u| u.user_room)
+    pub async fn is_user_in(&self, user_id: i64, room_id: i64) -> bool {
+        let users = self.users.read(user_id).await;
+        users
+            .get(&user_id)
+            .is_some_and(|us| us.rooms.contains(&room_id))
+    }
+
+    pub async fn is_online(&self, user_id: i64) -> bool {
+        let users = self.users.read(user_id).await;
+        users.get(&user_id).is_some_and(|us| !us.clients.is_empty())
     }
 
-    fn get_room_chan(&mut self, room_id: i64) -> mpsc::Sender<RoomAction> {
-        if let Some(rs) = self.rooms.get(&room_id) {
-            rs.tx.clone()
-        } else {
-            self.create_room(room_id)
+    /// Get the number of clients of the user, or `None` if the user is not kept
+    pub async fn num_clients(&self, user_id: i64) -> Option<usize> {
+        let users = self.users.read(user_id).await;
+        users.get(&user_id).map(|us| us.clients.len())
+    }
+
+    /// Get the id of all users kept by this node
+    pub async fn users(&self) -> Vec<i64> {
+        let mut ids = Vec::new();
+        for shard in self.users.iter() {
+            ids.extend(shard.read().await.keys());
         }
+        ids
     }
 
-    fn create_room(&mut self, room_id: i64) -> mpsc::Sender<RoomAction> {
+    /// Wait until the room and user tasks have handled the queued actions
+    ///
+    /// Rooms are flushed before users, so that the events forwarded by the
+    /// rooms are also put to the clients.
+    pub async fn drain(&self) {
+        for shard in self.rooms.iter() {
+            let chans: Vec<_> = shard
+                .read()
+                .await
+                .values()
+                .filter_map(|rs| rs.task.as_ref())
+                .map(|rt| rt.tx.clone())
+                .collect();
+            for tx in chans {
+                let (reply, done) = oneshot::channel();
+                if tx.send(RoomAction::Flush(reply)).await.is_ok() {
+                    let _ = done.await;
+                }
+            }
+        }
+        for shard in self.users.iter() {
+            let chans: Vec<_> = shard
+                .read()
+                .await
+                .values()
+                .map(|us| us.tx.clone())
+                .collect();
+            for tx in chans {
+                let (reply, done) = oneshot::channel();
+                if tx.send(UserAction::Flush(reply)).await.is_ok() {
+                    let _ = done.await;
+                }
+            }
+        }
+    }
+
+    fn create_user() -> mpsc::Sender<UserAction> {
         let (tx, rx) = mpsc::channel(CHAN_CAPACITY);
 
-        let task = tokio::spawn(async move {
-            let mut chat_room = ChatRoom::new(rx);
-            chat_room.serve().await;
+        tokio::spawn(async move {
+            let mut chat_user = ChatUser::new(rx, MAX_BUFFERED_EVENT);
+            chat_user.serve().await;
         });
 
-        let room = RoomState::new(tx.clone(), task);
-        self.rooms.insert(room_id, room);
-
         tx
     }
+
+    /// Add the user to the room, and start the room task if the user is live
+    async fn join_room(
+        &self,
+        room_id: i64,
+        user_id: i64,
+        tx: mpsc::Sender<UserAction>,
+        live: bool,
+    ) -> Result<(), Error> {
+        let room = {
+            let mut rooms = self.rooms.write(room_id).await;
+            let rs = rooms.entry(room_id).or_default();
+            rs.users.insert(user_id, tx.clone());
+            if live {
+                rs.live.insert(user_id);
+            }
+            rs.start_task();
+            rs.task.as_ref().map(|rt| rt.tx.clone())
+        };
+
+        // adding a user twice is fine, e.g. if the task started with the user
+        if let Some(room) = room {
+            room.send(RoomAction::Add(user_id, tx)).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove the user from the room, and evict the room if no user is left
+    async fn leave_room(&self, room_id: i64, user_id: i64) -> Result<(), Error> {
+        let room = {
+            let mut rooms = self.rooms.write(room_id).await;
+            let Some(rs) = rooms.get_mut(&room_id) else {
+                return Ok(());
+            };
+            rs.users.remove(&user_id);
+            rs.live.remove(&user_id);
+            let room = rs.task.as_ref().map(|rt| rt.tx.clone());
+
+            // the task stops once the queued actions are done
+            if rs.users.is_empty() {
+                rooms.remove(&room_id);
+            } else {
+                rs.stop_task();
+            }
+            room
+        };
+
+        if let Some(room) = room {
+            room.send(RoomAction::Del(user_id)).await?;
+        }
+        Ok(())
+    }
+
+    /// Mark the user live or idle in the rooms, so that only the rooms with
+    /// a live client keep a task, and the others get one again on demand
+    async fn set_live(&self, room_ids: &HashSet<i64>, user_id: i64, live: bool) {
+        for &room_id in room_ids {
+            let mut rooms = self.rooms.write(room_id).await;
+            let Some(rs) = rooms.get_mut(&room_id) else {
+                continue;
+            };
+            if live {
+                rs.live.insert(user_id);
+                rs.start_task();
+            } else {
+                rs.live.remove(&user_id);
+                rs.stop_task();
+            }
+        }
+    }
 }
 
 struct UserState {
-    txs: HashMap<Uuid, mpsc::Sender<Message>>,
-    user_room: i64,      // user room id
-    rooms: HashSet<i64>, // joined room list
+    tx: mpsc::Sender<UserAction>, // channel of the user task
+    clients: HashSet<Uuid>,       // connected client list
+    token: String,                // token to resume the buffered events
+    idle_since: Option<Instant>,  // when the last client left
+    rooms: HashSet<i64>,          // joined room list
 }
 
 impl UserState {
-    fn new(client: &Client, rooms: Vec<i64>) -> Self {
-        let mut txs = HashMap::new();
-        txs.insert(client.id(), client.tx());
-
+    fn new(tx: mpsc::Sender<UserAction>, rooms: Vec<i64>) -> Self {
         let rooms = rooms.into_iter().collect();
 
         Self {
-            txs,
-            user_room: client.room_id(),
+            tx,
+            clients: HashSet::new(),
+            token: Uuid::new_v4().to_string(),
+            idle_since: None,
             rooms,
         }
     }
+
+    /// Attach the client to the user task and return whether it is the first client
+    async fn attach_client(
+        &mut self,
+        client: &Client,
+        cursor: Option<u64>,
+    ) -> Result<(bool, ResumeResponse), Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(UserAction::Attach(client.clone(), cursor, tx))
+            .await?;
+        let cursor = rx
+            .await
+            .map_err(|_| Error::SendMessage)?
+            .ok_or(Error::ResumeExpired)?;
+
+        let first = self.clients.is_empty();
+        self.clients.insert(client.id());
+        self.idle_since = None;
+
+        let rsp = ResumeResponse {
+            resume_token: self.token.clone(),
+            cursor,
+        };
+        Ok((first, rsp))
+    }
 }
 
+#[derive(Default)]
 struct RoomState {
-    tx: mpsc::Sender<RoomAction>,
-    task: JoinHandle<()>,
+    users: HashMap<i64, mpsc::Sender<UserAction>>, // users with their channels
+    live: HashSet<i64>,                            // users with a live client
+    task: Option<RoomTask>,                        // only kept while a user is live
+    stopping: Option<JoinHandle<()>>,              // task handling its last actions
 }
 
 impl RoomState {
-    fn new(tx: mpsc::Sender<RoomAction>, task: JoinHandle<()>) -> Self {
-        Self { tx, task }
+    /// Start the room task with the users if a user is live
+    fn start_task(&mut self) {
+        if self.task.is_some() || self.live.is_empty() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel(CHAN_CAPACITY);
+        let users = self.users.clone();
+        let stopping = self.stopping.take();
+        let handle = tokio::spawn(async move {
+            // the messages queued in the stopped task go first
+            if let Some(handle) = stopping {
+                let _ = handle.await;
+            }
+            let mut chat_room = ChatRoom::new(rx, users);
+            chat_room.serve().await;
+        });
+        self.task = Some(RoomTask { tx, handle });
+    }
+
+    /// Drop the room task if no user is live, which stops once the queued
+    /// actions are done
+    fn stop_task(&mut self) {
+        if !self.live.is_empty() {
+            return;
+        }
+        if let Some(rt) = self.task.take() {
+            self.stopping = Some(rt.handle);
+        }
+    }
+}
+
+struct RoomTask {
+    tx: mpsc::Sender<RoomAction>, // channel of the room task
+    handle: JoinHandle<()>,       // handle to abort the room task
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conn::mailbox::{Mailbox, SlowClientPolicy};
+    use crate::conn::Message;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn drain_events() {
+        let hub = HubState::default();
+        let stats = Arc::new(DropStats::default());
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats);
+        let client = Client::new(1, 2, mailbox);
+        hub.register_client(&client, vec![3]).await.unwrap();
+
+        let tx = hub.room_chan(3).await.unwrap();
+        for _ in 0..10 {
+            let msg = Message::from_text(r#"{"action":"typing"}"#.to_owned()).unwrap();
+            tx.send(RoomAction::Send(msg)).await.unwrap();
+        }
+
+        // the events are in the mailbox once the hub is drained
+        hub.drain().await;
+        for _ in 0..10 {
+            assert!(client.try_recv().is_some());
+        }
+        assert!(client.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn tell_or_keep() {
+        let hub = HubState::default();
+        let stats = Arc::new(DropStats::default());
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats.clone());
+        let online = Client::new(1, 11, mailbox);
+        hub.register_client(&online, vec![]).await.unwrap();
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats);
+        let left = Client::new(2, 12, mailbox);
+        hub.register_client(&left, vec![]).await.unwrap();
+        hub.unregister_client(&left).await.unwrap();
+
+        let msg = Message::from_text(r#"{"action":"presence"}"#.to_owned()).unwrap();
+        let mut kept = Vec::new();
+        hub.tell(&[1, 2, 3], &msg, true, |users| async { kept = users })
+            .await;
+        assert_eq!(kept, vec![2, 3]);
+
+        // the users of other nodes are left to them
+        hub.tell(&[1, 2, 3], &msg, false, |users| async { kept = users })
+            .await;
+        assert_eq!(kept, vec![2]);
+
+        hub.drain().await;
+        assert!(online.try_recv().is_some());
+        assert!(online.try_recv().is_some());
+        assert!(left.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn apply_membership_twice() {
+        let hub = HubState::default();
+        let stats = Arc::new(DropStats::default());
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats);
+        let client = Client::new(1, 2, mailbox);
+        hub.register_client(&client, vec![]).await.unwrap();
+
+        // the fan-out mode applies the changes of this node once more
+        hub.add_members(3, &[1]).await.unwrap();
+        hub.add_members(3, &[1]).await.unwrap();
+        assert!(hub.is_user_in(1, 3).await);
+
+        let tx = hub.room_chan(3).await.unwrap();
+        let msg = Message::from_text(r#"{"action":"typing"}"#.to_owned()).unwrap();
+        tx.send(RoomAction::Send(msg)).await.unwrap();
+        hub.drain().await;
+        assert!(client.try_recv().is_some());
+        assert!(client.try_recv().is_none());
+
+        hub.remove_members(3, &[1]).await.unwrap();
+        hub.remove_members(3, &[1]).await.unwrap();
+        assert!(!hub.is_user_in(1, 3).await);
+        assert!(hub.room_chan(3).await.is_none());
+
+        hub.add_members(4, &[1]).await.unwrap();
+        hub.delete_room(4, &[1]).await.unwrap();
+        hub.delete_room(4, &[1]).await.unwrap();
+        assert!(!hub.is_user_in(1, 4).await);
+        assert!(hub.room_chan(4).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn evict_idle_rooms() {
+        let hub = HubState::default();
+        let stats = Arc::new(DropStats::default());
+        let compress = CompressStats::default();
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats.clone());
+        let client = Client::new(1, 2, mailbox);
+        let (_, rsp) = hub.register_client(&client, vec![3]).await.unwrap();
+        assert!(hub.room_chan(3).await.is_some());
+
+        // the room task is stopped once no member has a live client
+        hub.unregister_client(&client).await.unwrap();
+        assert!(hub.room_chan(3).await.is_none());
+        let status = hub.status(&stats, &compress).await.unwrap();
+        assert_eq!((status.num_live_rooms, status.num_idle_rooms), (0, 1));
+
+        // the events are still buffered for the user to resume
+        let msg = Message::from_text(r#"{"action":"typing"}"#.to_owned()).unwrap();
+        hub.send(3, msg).await.unwrap();
+
+        let mailbox = Mailbox::new(CHAN_CAPACITY, SlowClientPolicy::DropOldest, stats.clone());
+        let client = Client::new(1, 4, mailbox);
+        hub.resume_client(&client, &rsp.resume_token, rsp.cursor)
+            .await
+            .unwrap();
+        assert!(hub.room_chan(3).await.is_some());
+        let status = hub.status(&stats, &compress).await.unwrap();
+        assert_eq!((status.num_live_rooms, status.num_idle_rooms), (1, 0));
+
+        hub.drain().await;
+        assert!(client.try_recv().is_some());
+        assert!(client.try_recv().is_none());
     }
 }
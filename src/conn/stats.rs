@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of the events dropped for the slow clients
+#[derive(Default)]
+pub struct DropStats {
+    dropped_events: AtomicU64,
+    slow_clients: AtomicU64,
+}
+
+impl DropStats {
+    /// Return the number of dropped events and slow clients
+    pub fn load(&self) -> (u64, u64) {
+        (
+            self.dropped_events.load(Ordering::Relaxed),
+            self.slow_clients.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn drop_events(&self, n: usize) {
+        self.dropped_events.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_slow_client(&self) {
+        self.slow_clients.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters of the bytes of the frames written to the clients that
+/// negotiated compression
+#[derive(Default, Debug)]
+pub struct CompressStats {
+    raw_bytes: AtomicU64,
+    sent_bytes: AtomicU64,
+}
+
+impl CompressStats {
+    /// Record the size of a frame before and after compression, which is
+    /// the same for a frame sent as it is
+    pub fn record(&self, raw: usize, sent: usize) {
+        self.raw_bytes.fetch_add(raw as u64, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(sent as u64, Ordering::Relaxed);
+    }
+
+    /// Return the ratio of the raw size to the size sent over all the frames
+    pub fn ratio(&self) -> f64 {
+        let raw = self.raw_bytes.load(Ordering::Relaxed);
+        let sent = self.sent_bytes.load(Ordering::Relaxed);
+        if sent == 0 {
+            1.0
+        } else {
+            raw as f64 / sent as f64
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::core::constant::{TYPING_EXPIRE_SECONDS, TYPING_REFRESH_SECONDS};
+use std::collections::{hash_map::Entry, HashMap};
+use tokio::time::{Duration, Instant};
+
+/// Users who are typing in the rooms
+///
+/// An entry expires unless the user refreshes it in time, and the expired
+/// ones are swept for the whole hub at once, so no timer is kept per entry.
+#[derive(Default)]
+pub struct TypingState {
+    entries: HashMap<(i64, i64), Instant>, // when the entry was refreshed
+}
+
+impl TypingState {
+    /// Record the user typing in the room
+    ///
+    /// Return whether the user just started typing, or `None` if the entry
+    /// was refreshed too recently to be refreshed again.
+    pub fn start(&mut self, room_id: i64, user_id: i64, now: Instant) -> Option<bool> {
+        match self.entries.entry((room_id, user_id)) {
+            Entry::Occupied(mut o) => {
+                if now.duration_since(*o.get()) < Duration::from_secs(TYPING_REFRESH_SECONDS) {
+                    return None;
+                }
+                o.insert(now);
+                Some(false)
+            }
+            Entry::Vacant(v) => {
+                v.insert(now);
+                Some(true)
+            }
+        }
+    }
+
+    /// Remove the entry and return whether the user was typing
+    pub fn stop(&mut self, room_id: i64, user_id: i64) -> bool {
+        self.entries.remove(&(room_id, user_id)).is_some()
+    }
+
+    /// Remove the entries that were not refreshed in time, and return them
+    pub fn sweep(&mut self, now: Instant) -> Vec<(i64, i64)> {
+        let expire = Duration::from_secs(TYPING_EXPIRE_SECONDS);
+        let mut expired = Vec::new();
+        self.entries.retain(|&key, refreshed| {
+            let alive = now.duration_since(*refreshed) < expire;
+            if !alive {
+                expired.push(key);
+            }
+            alive
+        });
+        expired
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_typing() {
+        let mut typing = TypingState::default();
+        let now = Instant::now();
+
+        assert_eq!(typing.start(1, 10, now), Some(true));
+        // refreshes are limited
+        assert_eq!(typing.start(1, 10, now), None);
+
+        let later = now + Duration::from_secs(TYPING_REFRESH_SECONDS);
+        assert_eq!(typing.start(1, 10, later), Some(false));
+
+        // a refreshed entry is not swept with the time it started
+        let expire = Duration::from_secs(TYPING_EXPIRE_SECONDS);
+        assert!(typing.sweep(now + expire).is_empty());
+        assert_eq!(typing.sweep(later + expire), vec![(1, 10)]);
+        assert!(!typing.stop(1, 10));
+    }
+
+    #[test]
+    fn stop_typing() {
+        let mut typing = TypingState::default();
+        let now = Instant::now();
+
+        typing.start(1, 10, now);
+        typing.start(2, 10, now);
+
+        assert!(typing.stop(1, 10));
+        let expire = Duration::from_secs(TYPING_EXPIRE_SECONDS);
+        assert_eq!(typing.sweep(now + expire), vec![(2, 10)]);
+        assert!(!typing.stop(2, 10));
+    }
+}
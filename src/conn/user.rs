@@ -0,0 +1,74 @@
+use super::message::Message;
+use super::{outbox::Outbox, Client};
+use std::collections::HashMap;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+pub enum UserAction {
+    /// Send message to all clients of the user and keep it for replay
+    Send(Message),
+    /// Attach a client, replaying the events after the cursor if given
+    ///
+    /// Replies with the latest cursor, or `None` if the missed events
+    /// can not be replayed.
+    Attach(Client, Option<u64>, oneshot::Sender<Option<u64>>),
+    /// Detach a client of the user
+    Detach(Uuid),
+    /// Reply once the actions queued before are done
+    Flush(oneshot::Sender<()>),
+}
+
+/// Fans out the events of a user to the user's clients
+///
+/// All events to the user pass through here, so that they are numbered
+/// in order and kept while the user is reconnecting. Events are put to
+/// the clients without waiting, so a slow client never stalls the rooms.
+pub struct ChatUser {
+    rx: mpsc::Receiver<UserAction>,
+    clients: HashMap<Uuid, Client>,
+    outbox: Outbox,
+}
+
+impl ChatUser {
+    pub fn new(rx: mpsc::Receiver<UserAction>, capacity: usize) -> Self {
+        Self {
+            rx,
+            clients: HashMap::default(),
+            outbox: Outbox::new(capacity),
+        }
+    }
+
+    pub async fn serve(&mut self) {
+        while let Some(action) = self.rx.recv().await {
+            self.process(action);
+        }
+    }
+
+    fn process(&mut self, action: UserAction) {
+        match action {
+            UserAction::Send(msg) => {
+                let msg = self.outbox.push(msg);
+                for client in self.clients.values() {
+                    client.push(msg.clone());
+                }
+            }
+            UserAction::Attach(client, cursor, reply) => {
+                if let Some(cursor) = cursor {
+                    let Some(events) = self.outbox.since(cursor) else {
+                        let _ = reply.send(None);
+                        return;
+                    };
+                    client.extend(events);
+                }
+                let _ = reply.send(Some(self.outbox.cursor()));
+                self.clients.insert(client.id(), client);
+            }
+            UserAction::Detach(uid) => {
+                self.clients.remove(&uid);
+            }
+            UserAction::Flush(reply) => {
+                let _ = reply.send(());
+            }
+        }
+    }
+}
@@ -1,9 +1,27 @@
 pub const IMAGE_KEY: &str = "image";
 pub const WS_SUB_PROTOCOL_KEY: &str = "chat";
+pub const WS_MSGPACK_PROTOCOL_KEY: &str = "chat.msgpack";
+pub const HUB_EVENT_CHANNEL: &str = "hub:events";
+pub const NODE_EXPIRE_SECONDS: usize = 30;
+pub const NODE_HEARTBEAT_SECONDS: u64 = 10;
 pub const CHAN_CAPACITY: usize = 100;
+pub const NUM_HUB_SHARDS: usize = 64;
+pub const MAX_BUFFERED_EVENT: usize = 500;
 pub const MAX_CACHED_MESSAGE: isize = 60;
+pub const NUM_INITIAL_MESSAGE: isize = 30;
+pub const MAX_OFFLINE_EVENT: isize = 200;
+pub const OFFLINE_EVENT_EXPIRE_SECONDS: usize = 7 * 24 * 60 * 60;
+pub const QUOTE_PREVIEW_LENGTH: i32 = 50;
+pub const SEARCH_MATCH_START: char = '\u{2}';
+pub const SEARCH_MATCH_STOP: char = '\u{3}';
+pub const SEARCH_HEADLINE_OPTIONS: &str = "StartSel=\u{2}, StopSel=\u{3}, MaxFragments=2";
 pub const DIVIDE_INTERVAL_MINUTE: i64 = 5;
 pub const MAX_AHEAD_MINUTE: i64 = 3;
+pub const TYPING_EXPIRE_SECONDS: u64 = 6;
+pub const TYPING_REFRESH_SECONDS: u64 = 1;
+pub const TYPING_SWEEP_SECONDS: u64 = 1;
+pub const SHUTDOWN_TIMEOUT_SECONDS: u64 = 10;
+pub const CLIENT_ID_EXPIRE_SECONDS: usize = 600;
 
 pub const PERSONAL_ROOM_NAME: &str = "My Device";
 pub const PERSONAL_ROOM_COVER: &str = "/cover/personal";
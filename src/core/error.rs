@@ -46,6 +46,14 @@ pub enum Error {
     #[error("Data not found")]
     NotFound,
 
+    // 405 Method Not Allowed
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+
+    // 410 Gone
+    #[error("Session can not be resumed")]
+    ResumeExpired,
+
     // 422 UnprocessableEntity
     #[error(transparent)]
     QueryRejection(#[from] QueryRejection),
@@ -53,6 +61,10 @@ pub enum Error {
     #[error(transparent)]
     JsonRejection(#[from] JsonRejection),
 
+    // 503 Service Unavailable
+    #[error("Server is restarting")]
+    ShuttingDown,
+
     // 500 Internal Server Error
     #[error("Argon2 internal error")]
     Argon2,
@@ -70,10 +82,10 @@ pub enum Error {
     Sqlx(#[from] sqlx::Error),
 
     #[error(transparent)]
-    IoError(#[from] std::io::Error),
+    Io(#[from] std::io::Error),
 
     #[error(transparent)]
-    MultipartError(#[from] MultipartError),
+    Multipart(#[from] MultipartError),
 
     #[error(transparent)]
     JwtToken(#[from] jsonwebtoken::errors::Error),
@@ -81,6 +93,9 @@ pub enum Error {
     #[error(transparent)]
     Serde(#[from] serde_json::error::Error),
 
+    #[error(transparent)]
+    MessagePack(#[from] rmp_serde::encode::Error),
+
     #[error("Failed to send websocket message")]
     SendMessage,
 }
@@ -116,10 +131,16 @@ impl Error {
             Error::Forbidden => StatusCode::FORBIDDEN,
             // 404
             Error::NotFound => StatusCode::NOT_FOUND,
+            // 405
+            Error::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            // 410
+            Error::ResumeExpired => StatusCode::GONE,
             // 413
-            Error::MultipartError(ref mult) => mult.status(),
+            Error::Multipart(ref mult) => mult.status(),
             // 422
             Error::QueryRejection(_) | Error::JsonRejection(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            // 503
+            Error::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
             _ => {
                 tracing::error!("{}", self.to_string());
                 return (
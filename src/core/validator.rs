@@ -35,12 +35,23 @@ pub fn validate_message_kind(kind: &str) -> Result<(), ValidationError> {
     validate_oneof(kind, &kinds)
 }
 
+/// Check whether str is a single emoji, including its skin tone variants
+pub fn validate_emoji(emoji: &str) -> Result<(), ValidationError> {
+    if emojis::get(emoji).is_some() {
+        Ok(())
+    } else {
+        let mut e = ValidationError::new("emoji");
+        e.message = Some(Cow::from("Must be a single emoji"));
+        Err(e)
+    }
+}
+
 pub fn validate_id_vec(ids: &Vec<i64>) -> Result<(), ValidationError> {
     let mut seen = HashSet::new();
     for &id in ids {
         if id < 1 || !seen.insert(id) {
             let mut e = ValidationError::new("vec");
-            let msg = format!("Must be greater than 0 and not contain duplicate numbers");
+            let msg = "Must be greater than 0 and not contain duplicate numbers".to_owned();
             e.message = Some(Cow::from(msg));
             return Err(e);
         }
@@ -59,3 +70,22 @@ fn validate_oneof(item: &str, items: &Vec<&str>) -> Result<(), ValidationError>
         Err(e)
     }
 }
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_emoji() {
+        assert!(validate_emoji("👍").is_ok());
+        assert!(validate_emoji("👍🏽").is_ok());
+        assert!(validate_emoji("👨‍👩‍👧").is_ok());
+
+        assert!(validate_emoji("").is_err());
+        assert!(validate_emoji("a").is_err());
+        assert!(validate_emoji("👍👍").is_err());
+        assert!(validate_emoji("<b>").is_err());
+    }
+}
@@ -4,5 +4,14 @@ mod core;
 mod store;
 mod util;
 
-pub use api::make_app;
+pub use api::{make_app, AppState};
 pub use util::config::Config;
+
+/// Internals of the hub used by the benchmarks in `benches`
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::conn::{
+        Client, CompressStats, DropStats, HubState, Mailbox, Message, RoomAction, SlowClientPolicy,
+    };
+}
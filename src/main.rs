@@ -1,5 +1,5 @@
-use server::{make_app, Config};
-use std::net::SocketAddr;
+use server::{make_app, AppState, Config};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::{net::TcpListener, signal};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -27,15 +27,16 @@ async fn main() {
     tracing::info!("listening on {}", addr);
 
     let listener = TcpListener::bind(addr).await.unwrap();
-    let app = make_app(config).await;
+    let state = AppState::new(config).await;
+    let app = make_app(state.clone());
 
     axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+        .with_graceful_shutdown(shutdown_signal(state))
         .await
         .expect("failed to start server");
 }
 
-async fn shutdown_signal() {
+async fn shutdown_signal(state: Arc<AppState>) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -59,4 +60,7 @@ async fn shutdown_signal() {
     }
 
     tracing::info!("signal received, starting graceful shutdown");
+
+    // tell the clients to reconnect before the connections are dropped
+    state.shutdown().await;
 }
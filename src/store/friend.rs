@@ -42,10 +42,10 @@ impl Store {
             r#"
                 SELECT
                     f.room_id, f.status, f.create_at,
-                    r.id AS r_id, r.username AS r_username,
-                    r.nickname AS r_nickname, r.avatar AS r_avatar,
-                    a.id AS a_id, a.username AS a_username,
-                    a.nickname AS a_nickname, a.avatar AS a_avatar
+                    r.id AS r_id, r.username AS r_username, r.nickname AS r_nickname,
+                    r.avatar AS r_avatar, r.last_seen AS r_last_seen,
+                    a.id AS a_id, a.username AS a_username, a.nickname AS a_nickname,
+                    a.avatar AS a_avatar, a.last_seen AS a_last_seen
                 FROM friends AS f
                     JOIN users AS r ON r.id = f.requester_id
                     JOIN users AS a ON a.id = f.addressee_id
@@ -108,6 +108,7 @@ impl Store {
                 unreads: 0,
                 members: Vec::new(),
                 messages: Vec::new(),
+                pins: Vec::new(),
             };
             rooms.insert(v.member_id, room);
         }
@@ -121,6 +122,26 @@ impl Store {
         Ok((room0, room1))
     }
 
+    /// Get id of all accepted friends of the user
+    pub async fn get_friend_ids(&self, user_id: i64) -> Result<Vec<i64>, Error> {
+        let friend_ids = sqlx::query_scalar!(
+            r#"
+                SELECT
+                    CASE WHEN requester_id = $1 THEN addressee_id ELSE requester_id END
+                FROM friends
+                WHERE
+                    (requester_id = $1 OR addressee_id = $1)
+                    AND status = $2
+            "#,
+            user_id,
+            STATUS_ACCEPTED,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(friend_ids.into_iter().flatten().collect())
+    }
+
     /// Create a new friendship which does not exist in the database
     pub async fn create_friend(
         &self,
@@ -232,14 +253,14 @@ impl Store {
         let res = sqlx::query!(
             r#"
                 INSERT INTO members
-                    (room_id, member_id, rank)
-                VALUES
-                    ($1, $2, $3), ($4, $5, $6)
+                    (room_id, member_id, rank, last_read_seq)
+                SELECT
+                    id, UNNEST(ARRAY[$2::bigint, $3::bigint]), $4, last_seq
+                FROM rooms
+                WHERE id = $1
             "#,
             friend.room_id,
             friend.requester_id,
-            RANK_MEMBER,
-            friend.room_id,
             friend.addressee_id,
             RANK_MEMBER,
         )
@@ -341,10 +362,12 @@ struct BiFriendInfoRow {
     r_username: String,
     r_nickname: String,
     r_avatar: String,
+    r_last_seen: Option<OffsetDateTime>,
     a_id: i64,
     a_username: String,
     a_nickname: String,
     a_avatar: String,
+    a_last_seen: Option<OffsetDateTime>,
 }
 
 impl From<BiFriendInfoRow> for (FriendInfo, FriendInfo) {
@@ -357,7 +380,9 @@ impl From<BiFriendInfoRow> for (FriendInfo, FriendInfo) {
             status: row.status.clone(),
             room_id: row.room_id,
             first: true,
-            create_at: row.create_at.clone(),
+            online: false,
+            last_seen: row.r_last_seen,
+            create_at: row.create_at,
         };
         let addressee = FriendInfo {
             id: row.a_id,
@@ -367,6 +392,8 @@ impl From<BiFriendInfoRow> for (FriendInfo, FriendInfo) {
             status: row.status,
             room_id: row.room_id,
             first: false,
+            online: false,
+            last_seen: row.a_last_seen,
             create_at: row.create_at,
         };
         (requester, addressee)
@@ -1,13 +1,12 @@
 //! Methods of Store for initialization
 
-use super::{cmp_member, cmp_room, FriendInfo, MessageInfo, RoomInfo, Store};
+use super::{cmp_member, cmp_room, FriendInfo, RoomInfo, Store};
 use crate::api::CreateUserRequest;
 use crate::core::constant::ROLE_ADMIN;
 use crate::core::{
     constant::{STATUS_ACCEPTED, STATUS_ADDING},
     Error,
 };
-use redis::AsyncCommands;
 use time::OffsetDateTime;
 
 impl Store {
@@ -42,16 +41,12 @@ impl Store {
         let rooms = self.get_user_rooms_members(user_id).await?;
 
         let mut rooms_info = Vec::new();
-        let mut con = self.client.get_async_connection().await?;
 
         for (i, mut room) in rooms {
-            let key = format!("room:{}", i);
-            let messages: Vec<String> = con.lrange(key, 0, 30).await?;
-            for m in messages.iter().rev() {
-                let msg = serde_json::from_str::<MessageInfo>(m)?;
-                room.messages.push(msg);
-                room.members.sort_by(cmp_member);
-            }
+            room.messages = self.get_latest_messages(i).await?;
+            self.fill_reactions(user_id, &mut room.messages).await?;
+            room.pins = self.get_pins(i).await?;
+            room.members.sort_by(cmp_member);
             rooms_info.push(room);
         }
 
@@ -66,7 +61,7 @@ impl Store {
             r#"
                 SELECT
                     f.room_id, f.status, f.create_at, u.id, u.username,
-                    u.nickname, u.avatar, (f.addressee_id = $1) AS first
+                    u.nickname, u.avatar, u.last_seen, (f.addressee_id = $1) AS first
                 FROM friends AS f
                     JOIN users AS u ON u.id = f.addressee_id
                 WHERE
@@ -86,7 +81,7 @@ impl Store {
             r#"
                 SELECT
                     f.room_id, f.status, f.create_at, u.id, u.username,
-                    u.nickname, u.avatar, (f.addressee_id = $1) AS first
+                    u.nickname, u.avatar, u.last_seen, (f.addressee_id = $1) AS first
                 FROM friends AS f
                     JOIN users AS u ON u.id = f.requester_id
                 WHERE
@@ -114,6 +109,7 @@ struct FriendInfoRow {
     status: String,
     room_id: i64,
     first: Option<bool>,
+    last_seen: Option<OffsetDateTime>,
     create_at: OffsetDateTime,
 }
 
@@ -127,6 +123,8 @@ impl From<FriendInfoRow> for FriendInfo {
             status: v.status,
             room_id: v.room_id,
             first: v.first.unwrap_or(false),
+            online: false,
+            last_seen: v.last_seen,
             create_at: v.create_at,
         }
     }
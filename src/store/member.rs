@@ -6,7 +6,7 @@ use crate::core::{
     Error, ResultExt,
 };
 use crate::{
-    api::{AddMembersRequest, DeleteMembersRequest},
+    api::{AddMembersRequest, DeleteMembersRequest, MarkReadRequest, MarkReadResponse},
     store::MemberInfo,
 };
 use std::collections::{hash_map::Entry, HashMap};
@@ -21,9 +21,13 @@ impl Store {
         let members_id = sqlx::query_scalar!(
             r#"
                 INSERT INTO members
-                    (room_id, member_id, rank)
-                SELECT * FROM
+                    (room_id, member_id, rank, last_read_seq)
+                SELECT
+                    m.room_id, m.member_id, m.rank, r.last_seq
+                FROM
                     UNNEST($1::bigint[], $2::bigint[], $3::varchar[])
+                        AS m(room_id, member_id, rank)
+                    JOIN rooms AS r ON r.id = m.room_id
                 ON CONFLICT (room_id, member_id) DO NOTHING
                 RETURNING member_id
             "#,
@@ -94,6 +98,71 @@ impl Store {
         .not_found()
     }
 
+    /// Move the read marker of the member forward and return the unreads
+    ///
+    /// The returned flag tells whether the marker was actually advanced.
+    pub async fn mark_read(
+        &self,
+        member_id: i64,
+        req: &MarkReadRequest,
+    ) -> Result<(MarkReadResponse, bool), Error> {
+        let row = sqlx::query!(
+            r#"
+                UPDATE members AS x
+                SET last_read_seq = GREATEST(y.last_read_seq, LEAST($3, r.last_seq))
+                FROM
+                    (
+                        SELECT room_id, member_id, last_read_seq
+                        FROM members
+                        WHERE room_id = $1 AND member_id = $2
+                        FOR UPDATE
+                    ) AS y
+                    JOIN rooms AS r ON r.id = y.room_id
+                WHERE
+                    x.room_id = y.room_id
+                    AND x.member_id = y.member_id
+                RETURNING
+                    y.last_read_seq AS old_read_seq, x.last_read_seq, r.last_seq
+            "#,
+            req.room_id,
+            member_id,
+            req.seq,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .not_found()?;
+
+        let rsp = MarkReadResponse {
+            room_id: req.room_id,
+            last_read_seq: row.last_read_seq,
+            unreads: row.last_seq - row.last_read_seq,
+        };
+        Ok((rsp, row.last_read_seq > row.old_read_seq))
+    }
+
+    /// Get the members who have read the message, except the sender
+    pub async fn get_readers(&self, message_id: i64) -> Result<Vec<MemberInfo>, Error> {
+        let readers = sqlx::query_as!(
+            MemberInfo,
+            r#"
+                SELECT
+                    u.id, u.nickname AS name, u.avatar, y.rank, y.join_at
+                FROM messages AS m
+                JOIN members AS y ON y.room_id = m.room_id
+                JOIN users AS u ON u.id = y.member_id
+                WHERE
+                    m.id = $1
+                    AND y.last_read_seq >= m.seq
+                    AND y.member_id <> m.sender_id
+            "#,
+            message_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(readers)
+    }
+
     pub async fn get_user_rooms_members(
         &self,
         user_id: i64,
@@ -103,16 +172,14 @@ impl Store {
             r#"
                 WITH rooms_cte AS (
                     SELECT
-                        id AS room_id, name, cover, category, create_at
-                    FROM rooms
-                    WHERE id IN (
-                        SELECT room_id
-                        FROM members
-                        WHERE member_id = $1
-                    )
+                        r.id AS room_id, r.name, r.cover, r.category, r.create_at,
+                        (r.last_seq - y.last_read_seq) AS unreads
+                    FROM rooms AS r
+                    JOIN members AS y ON y.room_id = r.id
+                    WHERE y.member_id = $1
                 )
                 SELECT
-                    room_id, name, cover, category, create_at,
+                    room_id, name, cover, category, create_at, unreads,
                     member_id, rank, join_at, nickname, avatar
                 FROM rooms_cte AS r,
                     LATERAL (
@@ -158,9 +225,10 @@ impl Store {
                             cover: member.avatar.clone(),
                             category: r.category,
                             create_at: r.create_at,
-                            unreads: 0,
+                            unreads: r.unreads.unwrap_or(0),
                             members: vec![member],
                             messages: Vec::new(),
+                            pins: Vec::new(),
                         }
                     } else {
                         RoomInfo {
@@ -169,9 +237,10 @@ impl Store {
                             cover: r.cover,
                             category: r.category,
                             create_at: r.create_at,
-                            unreads: 0,
+                            unreads: r.unreads.unwrap_or(0),
                             members: vec![member],
                             messages: Vec::new(),
+                            pins: Vec::new(),
                         }
                     };
                     v.insert(room);
@@ -189,6 +258,7 @@ struct RoomMemberRow {
     cover: String,
     category: String,
     create_at: OffsetDateTime,
+    unreads: Option<i64>,
     member_id: i64,
     nickname: String,
     avatar: String,
@@ -1,60 +1,1071 @@
 //! Methods of Store for managing chat messages
 
-use super::{model::MessageInfo, Store};
+use super::{
+    model::{
+        EditInfo, ForwardInfo, ForwardSource, Message, MessageInfo, QuoteInfo, SearchResultInfo,
+        UserInfo,
+    },
+    Store,
+};
 use crate::{
-    api::NewMessageRequest,
+    api::{
+        DeleteMessageResponse, EditMessageRequest, EditMessageResponse, ListMessagesRequest,
+        ListMessagesResponse, NewMessageRequest, SearchMessagesRequest, SearchMessagesResponse,
+        UnpinMessageResponse,
+    },
     core::{
-        constant::{DIVIDE_INTERVAL_MINUTE, MAX_CACHED_MESSAGE},
-        Error,
+        constant::{
+            CLIENT_ID_EXPIRE_SECONDS, DIVIDE_INTERVAL_MINUTE, MAX_CACHED_MESSAGE,
+            NUM_INITIAL_MESSAGE, QUOTE_PREVIEW_LENGTH, SEARCH_HEADLINE_OPTIONS, SEARCH_MATCH_START,
+            SEARCH_MATCH_STOP,
+        },
+        Error, ResultExt,
     },
 };
 use redis::AsyncCommands;
+use sqlx::{Postgres, Transaction};
 use time::OffsetDateTime;
 
 impl Store {
-    pub async fn cache_message(
+    /// Save a new message in the database and push it to the room cache
+    ///
+    /// Each message gets the next sequence number of the room, so that
+    /// clients can detect gaps in the messages they received.
+    ///
+    /// A message resent with the same client id is not saved again, and the
+    /// message sent before is returned instead, with `false` for not new.
+    pub async fn create_message(
+        &self,
+        user_id: i64,
+        req: NewMessageRequest,
+    ) -> Result<(MessageInfo, bool), Error> {
+        let Some(client_id) = req.client_id.clone() else {
+            return Ok((self.save_message(user_id, req).await?, true));
+        };
+
+        // redis only caches the messages sent with a client id
+        if let Some(message_id) = self.get_client_id(user_id, &client_id).await? {
+            return Ok((self.get_sent_message(user_id, message_id).await?, false));
+        }
+
+        let user = self.get_user(user_id).await?;
+        let mut transaction = self.pool.begin().await?;
+
+        // lock the room first, so that a resent message waits for the first one
+        sqlx::query_scalar!(
+            r#"
+                SELECT id
+                FROM rooms
+                WHERE id = $1
+                FOR UPDATE
+            "#,
+            req.room_id,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .not_found()?;
+
+        let sent = sqlx::query_scalar!(
+            r#"
+                SELECT id
+                FROM messages
+                WHERE
+                    sender_id = $1
+                    AND client_id = $2
+            "#,
+            user_id,
+            client_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let (message, new) = match sent {
+            Some(message_id) => {
+                transaction.rollback().await?;
+                (self.get_sent_message(user_id, message_id).await?, false)
+            }
+            None => {
+                let message = insert_message(&mut transaction, &user, req, None).await?;
+                transaction.commit().await?;
+                self.cache_message(&message).await?;
+                (message, true)
+            }
+        };
+
+        // the message is sent even if only the cache failed
+        if let Err(e) = self.set_client_id(user_id, &client_id, message.id).await {
+            tracing::error!("failed to cache client id {}: {}", client_id, e);
+        }
+        Ok((message, new))
+    }
+
+    /// Get the message sent before from the view of the user
+    async fn get_sent_message(&self, user_id: i64, message_id: i64) -> Result<MessageInfo, Error> {
+        self.get_messages_by_ids(user_id, &[message_id])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotFound)
+    }
+
+    /// Copy the messages into the target rooms on behalf of the user
+    ///
+    /// The copies remember the original sender and room, and a forwarded
+    /// message keeps the provenance of its own source.
+    pub async fn forward_messages(
+        &self,
+        user_id: i64,
+        sources: Vec<ForwardSource>,
+        rooms_id: &[i64],
+    ) -> Result<Vec<MessageInfo>, Error> {
+        let user = self.get_user(user_id).await?;
+        let mut messages = Vec::new();
+
+        // lock the rooms in a fixed order to avoid deadlocks between forwards
+        let mut rooms_id = rooms_id.to_vec();
+        rooms_id.sort_unstable();
+
+        // save all copies at once, so none is sent if one of them fails
+        let mut transaction = self.pool.begin().await?;
+        for room_id in rooms_id {
+            for source in &sources {
+                let req = NewMessageRequest {
+                    room_id,
+                    content: source.content.clone(),
+                    file_url: source.file_url.clone(),
+                    kind: source.kind.clone(),
+                    parent_id: None,
+                    client_id: None,
+                };
+                let message =
+                    insert_message(&mut transaction, &user, req, Some(source.forward.clone()))
+                        .await?;
+                messages.push(message);
+            }
+        }
+        transaction.commit().await?;
+
+        for message in &messages {
+            self.cache_message(message).await?;
+        }
+
+        Ok(messages)
+    }
+
+    /// Get the messages to be forwarded in chronological order
+    pub async fn get_forward_sources(
+        &self,
+        messages_id: &[i64],
+    ) -> Result<Vec<ForwardSource>, Error> {
+        let rows = sqlx::query_as!(
+            ForwardSourceRow,
+            r#"
+                SELECT
+                    m.room_id, m.content, m.file_url, m.kind, m.deleted,
+                    COALESCE(m.forward_sender_id, m.sender_id) AS forward_sender_id,
+                    COALESCE(m.forward_room_id, m.room_id) AS forward_room_id,
+                    u.nickname AS forward_name
+                FROM messages AS m
+                JOIN users AS u ON u.id = COALESCE(m.forward_sender_id, m.sender_id)
+                WHERE m.id = ANY($1)
+                ORDER BY m.room_id, m.seq
+            "#,
+            messages_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        // every message must exist and not be recalled
+        if rows.len() != messages_id.len() || rows.iter().any(|x| x.deleted) {
+            return Err(Error::NotFound);
+        }
+
+        Ok(rows.into_iter().map(|x| x.into()).collect())
+    }
+
+    /// Save the message in its own transaction and push it to the room cache
+    async fn save_message(
         &self,
         user_id: i64,
         req: NewMessageRequest,
     ) -> Result<MessageInfo, Error> {
         let user = self.get_user(user_id).await?;
 
+        let mut transaction = self.pool.begin().await?;
+        let message = insert_message(&mut transaction, &user, req, None).await?;
+        transaction.commit().await?;
+
+        self.cache_message(&message).await?;
+        Ok(message)
+    }
+
+    /// Get the latest messages of the room in chronological order
+    ///
+    /// The messages are read from redis, and the cache will be refilled
+    /// from the database if it is missing or can not be parsed.
+    pub async fn get_latest_messages(&self, room_id: i64) -> Result<Vec<MessageInfo>, Error> {
         let mut con = self.client.get_async_connection().await?;
-        let key = format!("room:{}", req.room_id);
-
-        // check whether the last message was sent 5 minutes ago
-        let last: Vec<String> = con.lrange(key.clone(), 0, 0).await?;
-        let send_at = OffsetDateTime::now_utc();
-        let divide = if let Some(last_msg_str) = last.first() {
-            let msg = serde_json::from_str::<MessageInfo>(last_msg_str)?;
-            if (send_at - msg.send_at).whole_minutes() > DIVIDE_INTERVAL_MINUTE {
-                true
-            } else {
-                false
+        let key = format!("room:{}", room_id);
+
+        let cached: Vec<String> = con.lrange(key.as_str(), 0, NUM_INITIAL_MESSAGE - 1).await?;
+        if !cached.is_empty() {
+            let parsed: Result<Vec<MessageInfo>, _> = cached
+                .iter()
+                .map(|m| serde_json::from_str::<MessageInfo>(m))
+                .collect();
+
+            if let Ok(mut messages) = parsed {
+                messages.sort_by_key(|m| m.seq);
+                return Ok(messages);
             }
+            let _: () = con.del(key.as_str()).await?;
+        }
+
+        // a message cached after this bumps the version, and stops the refill
+        let version_key = format!("room:{}:version", room_id);
+        let version: Option<i64> = con.get(version_key.as_str()).await?;
+
+        // load the latest messages from database
+        let messages_id = sqlx::query_scalar!(
+            r#"
+                SELECT id
+                FROM messages
+                WHERE room_id = $1
+                ORDER BY seq DESC
+                LIMIT $2
+            "#,
+            room_id,
+            MAX_CACHED_MESSAGE as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut messages = self.load_messages(&messages_id).await?;
+
+        // warm up the cache with the newest message at the head, unless a
+        // message was cached meanwhile, or another reader refilled it first
+        if !messages.is_empty() {
+            let refill = redis::Script::new(
+                r#"
+                    local version = redis.call('GET', KEYS[2]) or '0'
+                    if version == ARGV[1] and redis.call('EXISTS', KEYS[1]) == 0 then
+                        redis.call('RPUSH', KEYS[1], unpack(ARGV, 2))
+                    end
+                "#,
+            );
+
+            let mut invocation = refill.prepare_invoke();
+            invocation
+                .key(key)
+                .key(version_key)
+                .arg(version.unwrap_or(0));
+            for m in messages.iter().rev() {
+                invocation.arg(serde_json::to_string(m)?);
+            }
+            let _: () = invocation.invoke_async(&mut con).await?;
+        }
+
+        let skip = messages.len().saturating_sub(NUM_INITIAL_MESSAGE as usize);
+        messages.drain(..skip);
+        Ok(messages)
+    }
+
+    /// Get a page of messages in the room before or after the cursor
+    ///
+    /// The cursor is the sequence number of a message in the room, and
+    /// the page is returned in chronological order.
+    pub async fn list_messages(
+        &self,
+        user_id: i64,
+        req: &ListMessagesRequest,
+    ) -> Result<ListMessagesResponse, Error> {
+        if req.before.is_some() && req.after.is_some() {
+            return Err(Error::BadRequest);
+        }
+
+        // fetch one more row to know whether there are more messages
+        let limit = req.page_size + 1;
+
+        let mut messages_id = if let Some(after) = req.after {
+            sqlx::query_scalar!(
+                r#"
+                    SELECT id
+                    FROM messages
+                    WHERE
+                        room_id = $1
+                        AND seq > $2
+                    ORDER BY seq ASC
+                    LIMIT $3
+                "#,
+                req.room_id,
+                after,
+                limit,
+            )
+            .fetch_all(&self.pool)
+            .await?
         } else {
-            true
+            sqlx::query_scalar!(
+                r#"
+                    SELECT id
+                    FROM messages
+                    WHERE
+                        room_id = $1
+                        AND ($2::bigint IS NULL OR seq < $2)
+                    ORDER BY seq DESC
+                    LIMIT $3
+                "#,
+                req.room_id,
+                req.before,
+                limit,
+            )
+            .fetch_all(&self.pool)
+            .await?
         };
 
-        // create new message
-        let message = MessageInfo {
+        let has_more = messages_id.len() as i64 > req.page_size;
+        messages_id.truncate(req.page_size as usize);
+        let mut messages = self.load_messages(&messages_id).await?;
+        self.fill_reactions(user_id, &mut messages).await?;
+
+        let rsp = ListMessagesResponse {
             room_id: req.room_id,
-            sender_id: user.id,
-            name: user.nickname,
-            avatar: user.avatar,
-            content: req.content,
-            file_url: req.file_url,
-            kind: req.kind,
-            divide,
-            send_at,
+            messages,
+            has_more,
+        };
+        Ok(rsp)
+    }
+
+    /// Search messages by keyword in the rooms the user belongs to
+    pub async fn search_messages(
+        &self,
+        user_id: i64,
+        req: &SearchMessagesRequest,
+    ) -> Result<SearchMessagesResponse, Error> {
+        // fetch one more row to know whether there are more results
+        let limit = req.page_size + 1;
+
+        let mut results: Vec<SearchResultInfo> = sqlx::query_as!(
+            SearchResultRow,
+            r#"
+                SELECT
+                    m.id, m.room_id, m.seq, m.sender_id, u.nickname AS name,
+                    u.avatar, m.kind,
+                    ts_headline('simple', translate(m.content, E'\x02\x03', ''), q, $2)
+                        AS snippet,
+                    m.send_at
+                FROM messages AS m
+                JOIN members AS y ON y.room_id = m.room_id AND y.member_id = $1
+                JOIN users AS u ON u.id = m.sender_id,
+                    websearch_to_tsquery('simple', $3) AS q
+                WHERE
+                    to_tsvector('simple', m.content) @@ q
+                    AND NOT m.deleted
+                    AND ($4::bigint IS NULL OR m.room_id = $4)
+                    AND ($5::bigint IS NULL OR m.sender_id = $5)
+                    AND ($6::varchar IS NULL OR m.kind = $6)
+                    AND ($7::timestamptz IS NULL OR m.send_at >= $7)
+                    AND ($8::timestamptz IS NULL OR m.send_at < $8)
+                    AND ($9::bigint IS NULL OR m.id < $9)
+                ORDER BY m.id DESC
+                LIMIT $10
+            "#,
+            user_id,
+            SEARCH_HEADLINE_OPTIONS,
+            req.keyword,
+            req.room_id,
+            req.sender_id,
+            req.kind,
+            req.since,
+            req.until,
+            req.before,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map(|arr| arr.into_iter().map(|x| x.into()).collect())?;
+
+        let has_more = results.len() as i64 > req.page_size;
+        results.truncate(req.page_size as usize);
+
+        let rsp = SearchMessagesResponse { results, has_more };
+        Ok(rsp)
+    }
+
+    /// Get all replies in the thread of the root message in chronological order
+    pub async fn list_replies(
+        &self,
+        user_id: i64,
+        root_id: i64,
+    ) -> Result<Vec<MessageInfo>, Error> {
+        let replies_id = sqlx::query_scalar!(
+            r#"
+                WITH RECURSIVE thread AS (
+                    SELECT id
+                    FROM messages
+                    WHERE parent_id = $1
+                    UNION
+                    SELECT c.id
+                    FROM messages AS c
+                    JOIN thread AS t ON c.parent_id = t.id
+                )
+                SELECT id AS "id!"
+                FROM thread
+            "#,
+            root_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut replies = self.load_messages(&replies_id).await?;
+
+        self.fill_reactions(user_id, &mut replies).await?;
+        Ok(replies)
+    }
+
+    /// Get the messages by id from the view of the user
+    pub async fn get_messages_by_ids(
+        &self,
+        user_id: i64,
+        messages_id: &[i64],
+    ) -> Result<Vec<MessageInfo>, Error> {
+        let mut messages = self.load_messages(messages_id).await?;
+        self.fill_reactions(user_id, &mut messages).await?;
+        Ok(messages)
+    }
+
+    /// Load the messages with their quotes and provenance by id
+    ///
+    /// This is the only query that builds `MessageInfo` from the database,
+    /// and the messages are ordered by room and sequence number.
+    async fn load_messages(&self, messages_id: &[i64]) -> Result<Vec<MessageInfo>, Error> {
+        let messages = sqlx::query_as!(
+            MessageRow,
+            r#"
+                SELECT
+                    m.id, m.room_id, m.seq, m.sender_id, u.nickname AS name,
+                    u.avatar, m.content, m.file_url, m.kind, m.divide, m.deleted,
+                    m.edit_at, m.send_at, p.id AS quote_id, p.sender_id AS quote_sender_id,
+                    pu.nickname AS quote_name, p.kind AS quote_kind,
+                    left(p.content, $2) AS quote_content, p.deleted AS quote_deleted,
+                    m.forward_sender_id, fu.nickname AS forward_name, m.forward_room_id
+                FROM messages AS m
+                JOIN users AS u ON u.id = m.sender_id
+                LEFT JOIN messages AS p ON p.id = m.parent_id
+                LEFT JOIN users AS pu ON pu.id = p.sender_id
+                LEFT JOIN users AS fu ON fu.id = m.forward_sender_id
+                WHERE m.id = ANY($1)
+                ORDER BY m.room_id, m.seq
+            "#,
+            messages_id,
+            QUOTE_PREVIEW_LENGTH,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map(|arr| arr.into_iter().map(|x| x.into()).collect())?;
+
+        Ok(messages)
+    }
+
+    pub async fn get_message(&self, message_id: i64) -> Result<Message, Error> {
+        sqlx::query_as!(
+            Message,
+            r#"
+                SELECT
+                    id, room_id, sender_id, kind, deleted, send_at
+                FROM messages
+                WHERE id = $1
+            "#,
+            message_id,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .not_found()
+    }
+
+    /// Replace the content of the message and keep the old one in history
+    pub async fn edit_message(
+        &self,
+        req: &EditMessageRequest,
+    ) -> Result<EditMessageResponse, Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        // save the current content in the edit history
+        sqlx::query!(
+            r#"
+                INSERT INTO message_edits
+                    (message_id, content)
+                SELECT id, content
+                FROM messages
+                WHERE id = $1
+            "#,
+            req.message_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        let edit_at = OffsetDateTime::now_utc();
+        let room_id = sqlx::query_scalar!(
+            r#"
+                UPDATE messages
+                SET content = $1, edit_at = $2
+                WHERE
+                    id = $3
+                    AND NOT deleted
+                RETURNING room_id
+            "#,
+            req.content,
+            edit_at,
+            req.message_id,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .not_found()?;
+
+        transaction.commit().await?;
+
+        let preview: String = req
+            .content
+            .chars()
+            .take(QUOTE_PREVIEW_LENGTH as usize)
+            .collect();
+        self.update_cached_message(
+            room_id,
+            req.message_id,
+            |m| {
+                m.content = req.content.clone();
+                m.edit_at = Some(edit_at);
+            },
+            |q| q.content = preview.clone(),
+        )
+        .await?;
+
+        let rsp = EditMessageResponse {
+            room_id,
+            message_id: req.message_id,
+            content: req.content.clone(),
+            edit_at,
         };
-        let msg_str = serde_json::to_string(&message)?;
+        Ok(rsp)
+    }
+
+    /// Get the earlier contents of the message with the oldest first
+    pub async fn get_edits(&self, message_id: i64) -> Result<Vec<EditInfo>, Error> {
+        let edits = sqlx::query_as!(
+            EditInfo,
+            r#"
+                SELECT content, edit_at
+                FROM message_edits
+                WHERE message_id = $1
+                ORDER BY id
+            "#,
+            message_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(edits)
+    }
+
+    /// Recall the message by removing its content
+    ///
+    /// The edit history is kept for the record, but no longer listed.
+    ///
+    /// The pin of the message is removed as well, and returned if it existed.
+    pub async fn delete_message(
+        &self,
+        message_id: i64,
+    ) -> Result<(DeleteMessageResponse, Option<UnpinMessageResponse>), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let rsp = sqlx::query_as!(
+            DeleteMessageResponse,
+            r#"
+                UPDATE messages
+                SET content = '', file_url = '', deleted = true
+                WHERE
+                    id = $1
+                    AND NOT deleted
+                RETURNING
+                    id AS message_id, room_id
+            "#,
+            message_id,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .not_found()?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM reactions
+                WHERE message_id = $1
+            "#,
+            message_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
 
-        let total: isize = con.lpush(key.as_str(), msg_str).await?;
+        let unpin = sqlx::query_as!(
+            UnpinMessageResponse,
+            r#"
+                DELETE FROM pins
+                WHERE message_id = $1
+                RETURNING room_id, message_id
+            "#,
+            message_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+        self.update_cached_message(
+            rsp.room_id,
+            message_id,
+            |m| {
+                m.content.clear();
+                m.file_url.clear();
+                m.deleted = true;
+                m.reactions.clear();
+            },
+            |q| {
+                q.content.clear();
+                q.deleted = true;
+            },
+        )
+        .await?;
+
+        Ok((rsp, unpin))
+    }
+
+    /// Get the room id of the message
+    pub async fn get_message_room(&self, message_id: i64) -> Result<i64, Error> {
+        sqlx::query_scalar!(
+            r#"
+                SELECT room_id
+                FROM messages
+                WHERE id = $1
+            "#,
+            message_id,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .not_found()
+    }
+
+    /// Get the id of the message sent with the client id from the cache
+    async fn get_client_id(&self, user_id: i64, client_id: &str) -> Result<Option<i64>, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("client:{}:{}", user_id, client_id);
+        let message_id: Option<i64> = con.get(key).await?;
+        Ok(message_id)
+    }
+
+    /// Cache the id of the message sent with the client id
+    async fn set_client_id(
+        &self,
+        user_id: i64,
+        client_id: &str,
+        message_id: i64,
+    ) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("client:{}:{}", user_id, client_id);
+        let _: () = con
+            .set_ex(key, message_id, CLIENT_ID_EXPIRE_SECONDS)
+            .await?;
+        Ok(())
+    }
+
+    /// Rewrite the message and the quotes of it in the room cache
+    ///
+    /// An entry is only replaced if it has not changed since it was read,
+    /// otherwise the cache is removed and will be refilled on the next read.
+    async fn update_cached_message<M, Q>(
+        &self,
+        room_id: i64,
+        message_id: i64,
+        update: M,
+        update_quote: Q,
+    ) -> Result<(), Error>
+    where
+        M: Fn(&mut MessageInfo),
+        Q: Fn(&mut QuoteInfo),
+    {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("room:{}", room_id);
+
+        let replace = redis::Script::new(
+            r#"
+                if redis.call('LINDEX', KEYS[1], ARGV[1]) == ARGV[2] then
+                    redis.call('LSET', KEYS[1], ARGV[1], ARGV[3])
+                    return 1
+                end
+                return 0
+            "#,
+        );
+
+        let cached: Vec<String> = con.lrange(key.as_str(), 0, -1).await?;
+        for (index, item) in cached.iter().enumerate() {
+            let Ok(mut message) = serde_json::from_str::<MessageInfo>(item) else {
+                continue;
+            };
+
+            let quoted = message.quote.as_ref().is_some_and(|q| q.id == message_id);
+            if message.id != message_id && !quoted {
+                continue;
+            }
+            if message.id == message_id {
+                update(&mut message);
+            }
+            if let Some(quote) = message.quote.as_mut().filter(|_| quoted) {
+                update_quote(quote);
+            }
+
+            let replaced: bool = replace
+                .key(key.as_str())
+                .arg(index)
+                .arg(item)
+                .arg(serde_json::to_string(&message)?)
+                .invoke_async(&mut con)
+                .await?;
+            if !replaced {
+                let _: () = con.del(key.as_str()).await?;
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Push the message to the head of the room cache if the cache exists
+    async fn cache_message(&self, message: &MessageInfo) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("room:{}", message.room_id);
+        let version_key = format!("room:{}:version", message.room_id);
+        let msg_str = serde_json::to_string(message)?;
+
+        // the cache is only pushed if it exists, and a refill in progress is
+        // told by the version that it misses this message
+        let (total,): (isize,) = redis::pipe()
+            .atomic()
+            .incr(version_key, 1)
+            .ignore()
+            .lpush_exists(key.as_str(), msg_str)
+            .query_async(&mut con)
+            .await?;
         if total > MAX_CACHED_MESSAGE {
-            con.ltrim(key, 0, 19).await?;
+            let _: () = con.ltrim(key, 0, MAX_CACHED_MESSAGE - 1).await?;
         }
 
-        Ok(message)
+        Ok(())
+    }
+}
+
+/// Save the message in the transaction and attach the provenance if it is forwarded
+async fn insert_message(
+    transaction: &mut Transaction<'_, Postgres>,
+    user: &UserInfo,
+    req: NewMessageRequest,
+    forward: Option<ForwardInfo>,
+) -> Result<MessageInfo, Error> {
+    // increase the room sequence, which also locks the room row
+    let seq = sqlx::query_scalar!(
+        r#"
+            UPDATE rooms
+            SET last_seq = last_seq + 1
+            WHERE id = $1
+            RETURNING last_seq
+        "#,
+        req.room_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .not_found()?;
+
+    // check whether the last message was sent 5 minutes ago
+    let last_send_at = sqlx::query_scalar!(
+        r#"
+            SELECT send_at
+            FROM messages
+            WHERE room_id = $1
+            ORDER BY seq DESC
+            LIMIT 1
+        "#,
+        req.room_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    let send_at = OffsetDateTime::now_utc();
+    let divide = match last_send_at {
+        Some(last) => (send_at - last).whole_minutes() > DIVIDE_INTERVAL_MINUTE,
+        None => true,
+    };
+
+    // the quoted message must be in the same room
+    let quote = if let Some(parent_id) = req.parent_id {
+        let row = sqlx::query_as!(
+            QuoteRow,
+            r#"
+                SELECT
+                    p.id, p.room_id, p.sender_id, u.nickname AS name, p.kind,
+                    left(p.content, $2) AS content, p.deleted
+                FROM messages AS p
+                JOIN users AS u ON u.id = p.sender_id
+                WHERE p.id = $1
+            "#,
+            parent_id,
+            QUOTE_PREVIEW_LENGTH,
+        )
+        .fetch_one(&mut **transaction)
+        .await
+        .not_found()?;
+
+        if row.room_id != req.room_id || row.deleted {
+            return Err(Error::BadRequest);
+        }
+        Some(row.into())
+    } else {
+        None
+    };
+
+    // save the message in database
+    let id = sqlx::query_scalar!(
+        r#"
+            INSERT INTO messages
+                (room_id, seq, sender_id, parent_id, forward_sender_id, forward_room_id,
+                content, file_url, kind, divide, send_at, client_id)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING id
+        "#,
+        req.room_id,
+        seq,
+        user.id,
+        req.parent_id,
+        forward.as_ref().map(|x| x.sender_id),
+        forward.as_ref().map(|x| x.room_id),
+        req.content,
+        req.file_url,
+        req.kind,
+        divide,
+        send_at,
+        req.client_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    // the sender has read the room up to the new message
+    sqlx::query!(
+        r#"
+            UPDATE members
+            SET last_read_seq = $3
+            WHERE
+                room_id = $1
+                AND member_id = $2
+        "#,
+        req.room_id,
+        user.id,
+        seq,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    let message = MessageInfo {
+        id,
+        room_id: req.room_id,
+        seq,
+        sender_id: user.id,
+        name: user.nickname.clone(),
+        avatar: user.avatar.clone(),
+        content: req.content,
+        file_url: req.file_url,
+        kind: req.kind,
+        divide,
+        deleted: false,
+        edit_at: None,
+        quote,
+        forward,
+        reactions: Vec::new(),
+        send_at,
+    };
+    Ok(message)
+}
+
+// ========================// Conversions //======================== //
+
+struct MessageRow {
+    id: i64,
+    room_id: i64,
+    seq: i64,
+    sender_id: i64,
+    name: String,
+    avatar: String,
+    content: String,
+    file_url: String,
+    kind: String,
+    divide: bool,
+    deleted: bool,
+    edit_at: Option<OffsetDateTime>,
+    send_at: OffsetDateTime,
+    quote_id: Option<i64>,
+    quote_sender_id: Option<i64>,
+    quote_name: Option<String>,
+    quote_kind: Option<String>,
+    quote_content: Option<String>,
+    quote_deleted: Option<bool>,
+    forward_sender_id: Option<i64>,
+    forward_name: Option<String>,
+    forward_room_id: Option<i64>,
+}
+
+impl From<MessageRow> for MessageInfo {
+    fn from(v: MessageRow) -> Self {
+        let quote = v.quote_id.map(|id| QuoteInfo {
+            id,
+            sender_id: v.quote_sender_id.unwrap_or(0),
+            name: v.quote_name.unwrap_or_default(),
+            kind: v.quote_kind.unwrap_or_default(),
+            content: v.quote_content.unwrap_or_default(),
+            deleted: v.quote_deleted.unwrap_or(false),
+        });
+        let forward = match (v.forward_sender_id, v.forward_room_id) {
+            (Some(sender_id), Some(room_id)) => Some(ForwardInfo {
+                sender_id,
+                name: v.forward_name.unwrap_or_default(),
+                room_id,
+            }),
+            _ => None,
+        };
+
+        Self {
+            id: v.id,
+            room_id: v.room_id,
+            seq: v.seq,
+            sender_id: v.sender_id,
+            name: v.name,
+            avatar: v.avatar,
+            content: v.content,
+            file_url: v.file_url,
+            kind: v.kind,
+            divide: v.divide,
+            deleted: v.deleted,
+            edit_at: v.edit_at,
+            quote,
+            forward,
+            reactions: Vec::new(),
+            send_at: v.send_at,
+        }
+    }
+}
+
+struct ForwardSourceRow {
+    room_id: i64,
+    content: String,
+    file_url: String,
+    kind: String,
+    deleted: bool,
+    forward_sender_id: Option<i64>,
+    forward_room_id: Option<i64>,
+    forward_name: String,
+}
+
+impl From<ForwardSourceRow> for ForwardSource {
+    fn from(v: ForwardSourceRow) -> Self {
+        Self {
+            room_id: v.room_id,
+            content: v.content,
+            file_url: v.file_url,
+            kind: v.kind,
+            forward: ForwardInfo {
+                sender_id: v.forward_sender_id.unwrap_or(0),
+                name: v.forward_name,
+                room_id: v.forward_room_id.unwrap_or(0),
+            },
+        }
+    }
+}
+
+struct SearchResultRow {
+    id: i64,
+    room_id: i64,
+    seq: i64,
+    sender_id: i64,
+    name: String,
+    avatar: String,
+    kind: String,
+    snippet: Option<String>,
+    send_at: OffsetDateTime,
+}
+
+impl From<SearchResultRow> for SearchResultInfo {
+    fn from(v: SearchResultRow) -> Self {
+        Self {
+            id: v.id,
+            room_id: v.room_id,
+            seq: v.seq,
+            sender_id: v.sender_id,
+            name: v.name,
+            avatar: v.avatar,
+            kind: v.kind,
+            snippet: mark_snippet(&v.snippet.unwrap_or_default()),
+            send_at: v.send_at,
+        }
+    }
+}
+
+/// Escape the snippet as HTML and wrap the matches in `<mark>` tags
+///
+/// The matches are delimited by control characters, which are removed
+/// from the content before the headline is built.
+fn mark_snippet(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            SEARCH_MATCH_START => html.push_str("<mark>"),
+            SEARCH_MATCH_STOP => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(c),
+        }
+    }
+    html
+}
+
+struct QuoteRow {
+    id: i64,
+    room_id: i64,
+    sender_id: i64,
+    name: String,
+    kind: String,
+    content: Option<String>,
+    deleted: bool,
+}
+
+impl From<QuoteRow> for QuoteInfo {
+    fn from(v: QuoteRow) -> Self {
+        Self {
+            id: v.id,
+            sender_id: v.sender_id,
+            name: v.name,
+            kind: v.kind,
+            content: v.content.unwrap_or_default(),
+            deleted: v.deleted,
+        }
+    }
+}
+
+// ============================== // tests // ============================== //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_snippet() {
+        let snippet = "a <b>\u{2}hello\u{3}</b> & \"x\"";
+        assert_eq!(
+            mark_snippet(snippet),
+            "a &lt;b&gt;<mark>hello</mark>&lt;/b&gt; &amp; &quot;x&quot;"
+        );
     }
 }
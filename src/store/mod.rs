@@ -9,7 +9,12 @@ mod init;
 mod member;
 mod message;
 mod model;
+mod offline;
+mod pin;
+mod presence;
+mod reaction;
 mod room;
+mod saved;
 mod session;
 mod user;
 
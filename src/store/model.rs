@@ -54,6 +54,7 @@ pub struct Friend {
     pub addressee_id: i64,
     pub room_id: i64,
     pub status: String,
+    #[allow(dead_code)]
     pub create_at: OffsetDateTime,
 }
 
@@ -67,6 +68,9 @@ pub struct FriendInfo {
     pub status: String,
     pub room_id: i64,
     pub first: bool,
+    pub online: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_seen: Option<OffsetDateTime>,
     #[serde(with = "time::serde::rfc3339")]
     pub create_at: OffsetDateTime,
 }
@@ -93,6 +97,7 @@ pub struct RoomInfo {
     pub create_at: OffsetDateTime,
     pub members: Vec<MemberInfo>,
     pub messages: Vec<MessageInfo>,
+    pub pins: Vec<PinInfo>,
 }
 
 impl From<(Room, Vec<MemberInfo>)> for RoomInfo {
@@ -106,6 +111,7 @@ impl From<(Room, Vec<MemberInfo>)> for RoomInfo {
             create_at: r.create_at,
             members: m,
             messages: Vec::new(),
+            pins: Vec::new(),
         }
     }
 }
@@ -153,10 +159,21 @@ pub fn cmp_member(a: &MemberInfo, b: &MemberInfo) -> Ordering {
 
 // ========================= // Message // ========================= //
 
+pub struct Message {
+    pub id: i64,
+    pub room_id: i64,
+    pub sender_id: i64,
+    pub kind: String,
+    pub deleted: bool,
+    pub send_at: OffsetDateTime,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageInfo {
+    pub id: i64,
     pub room_id: i64,
+    pub seq: i64,
     pub sender_id: i64,
     pub name: String,
     pub avatar: String,
@@ -164,6 +181,106 @@ pub struct MessageInfo {
     pub file_url: String,
     pub kind: String,
     pub divide: bool,
+    pub deleted: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub edit_at: Option<OffsetDateTime>,
+    pub quote: Option<QuoteInfo>,
+    pub forward: Option<ForwardInfo>,
+    #[serde(default)]
+    pub reactions: Vec<ReactionInfo>,
     #[serde(with = "time::serde::rfc3339")]
     pub send_at: OffsetDateTime,
 }
+
+/// Preview of the message quoted by a reply
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteInfo {
+    pub id: i64,
+    pub sender_id: i64,
+    pub name: String,
+    pub kind: String,
+    pub content: String,
+    pub deleted: bool,
+}
+
+/// Provenance of a forwarded message
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForwardInfo {
+    pub sender_id: i64,
+    pub name: String,
+    pub room_id: i64,
+}
+
+/// Message to be copied by forwarding
+pub struct ForwardSource {
+    pub room_id: i64,
+    pub content: String,
+    pub file_url: String,
+    pub kind: String,
+    pub forward: ForwardInfo,
+}
+
+/// Message saved by the user with the context of its room
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedMessageInfo {
+    pub id: i64,
+    pub room_id: i64,
+    pub room_name: String,
+    pub room_category: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub save_at: OffsetDateTime,
+    pub message: MessageInfo,
+}
+
+/// Message matched by a search with the highlighted snippet
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResultInfo {
+    pub id: i64,
+    pub room_id: i64,
+    pub seq: i64,
+    pub sender_id: i64,
+    pub name: String,
+    pub avatar: String,
+    pub kind: String,
+    pub snippet: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub send_at: OffsetDateTime,
+}
+
+/// Message pinned in the room
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinInfo {
+    pub message_id: i64,
+    pub seq: i64,
+    pub sender_id: i64,
+    pub name: String,
+    pub content: String,
+    pub file_url: String,
+    pub kind: String,
+    pub pinner_id: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub pin_at: OffsetDateTime,
+}
+
+/// Earlier content of an edited message
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditInfo {
+    pub content: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub edit_at: OffsetDateTime,
+}
+
+/// Aggregated reactions of an emoji on the message
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionInfo {
+    pub emoji: String,
+    pub count: i64,
+    pub reacted: bool,
+}
@@ -0,0 +1,131 @@
+//! Methods of Store for managing events of offline users
+
+use super::{presence::LIVE_CLIENTS_LUA, Store};
+use crate::core::{
+    constant::{MAX_OFFLINE_EVENT, OFFLINE_EVENT_EXPIRE_SECONDS},
+    Error,
+};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+impl Store {
+    /// Make the id of an event to keep for the offline users
+    ///
+    /// The id holds the time the event is sent and a random part, so that
+    /// identical events are all kept, while the same event kept twice for
+    /// a user, e.g. by two nodes, is only kept once.
+    pub fn offline_event_id() -> String {
+        let now = OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000;
+        format!("{}:{}", now, Uuid::new_v4().simple())
+    }
+
+    /// Keep the event for the offline users
+    ///
+    /// Events are scored by the time they are kept, and only the latest
+    /// ones that have not expired are kept. Each member is prefixed with
+    /// the id of the event.
+    pub async fn push_offline_events(
+        &self,
+        users: &[i64],
+        id: &str,
+        event: &str,
+    ) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+        let expire_at = now - OFFLINE_EVENT_EXPIRE_SECONDS as i64 * 1000;
+        let member = format!("{}:{}", id, event);
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for user_id in users {
+            let key = format!("offline:{}", user_id);
+            pipe.zadd(key.as_str(), member.as_str(), now)
+                .ignore()
+                .zrembyscore(key.as_str(), "-inf", expire_at)
+                .ignore()
+                .zremrangebyrank(key.as_str(), 0, -(MAX_OFFLINE_EVENT + 1))
+                .ignore()
+                .expire(key.as_str(), OFFLINE_EVENT_EXPIRE_SECONDS)
+                .ignore();
+        }
+        let _: () = pipe.query_async(&mut con).await?;
+
+        Ok(())
+    }
+
+    /// Keep the event for the users without a live client on any node, and
+    /// return the others
+    ///
+    /// Both are done in one script, so a user who leaves in between is not
+    /// taken as online and left without the event.
+    pub async fn keep_offline_event(
+        &self,
+        users: &[i64],
+        id: &str,
+        event: &str,
+    ) -> Result<Vec<i64>, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+        let expire_at = now - OFFLINE_EVENT_EXPIRE_SECONDS as i64 * 1000;
+
+        let script = redis::Script::new(&format!(
+            "{}{}",
+            LIVE_CLIENTS_LUA,
+            r#"
+                local online = {}
+                for i = 1, #KEYS, 2 do
+                    if live_clients(KEYS[i]) > 0 then
+                        table.insert(online, (i + 1) / 2)
+                    else
+                        redis.call('ZADD', KEYS[i + 1], ARGV[2], ARGV[1])
+                        redis.call('ZREMRANGEBYSCORE', KEYS[i + 1], '-inf', ARGV[3])
+                        redis.call('ZREMRANGEBYRANK', KEYS[i + 1], 0, -(tonumber(ARGV[4]) + 1))
+                        redis.call('EXPIRE', KEYS[i + 1], ARGV[5])
+                    end
+                end
+                return online
+            "#
+        ));
+
+        let mut invocation = script.prepare_invoke();
+        for user_id in users {
+            invocation
+                .key(format!("presence:{}", user_id))
+                .key(format!("offline:{}", user_id));
+        }
+        let online: Vec<usize> = invocation
+            .arg(format!("{}:{}", id, event))
+            .arg(now)
+            .arg(expire_at)
+            .arg(MAX_OFFLINE_EVENT)
+            .arg(OFFLINE_EVENT_EXPIRE_SECONDS)
+            .invoke_async(&mut con)
+            .await?;
+
+        // the script returns the 1-based positions of the online users
+        Ok(online.into_iter().map(|i| users[i - 1]).collect())
+    }
+
+    /// Take all the unexpired events of the user in the order they were sent
+    pub async fn take_offline_events(&self, user_id: i64) -> Result<Vec<String>, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("offline:{}", user_id);
+        let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+        let expire_at = now - OFFLINE_EVENT_EXPIRE_SECONDS as i64 * 1000;
+
+        let (members,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .zrangebyscore(key.as_str(), expire_at, "+inf")
+            .del(key.as_str())
+            .ignore()
+            .query_async(&mut con)
+            .await?;
+
+        // strip the prefix of the members
+        let events = members
+            .into_iter()
+            .filter_map(|m| m.splitn(3, ':').nth(2).map(|e| e.to_owned()))
+            .collect();
+        Ok(events)
+    }
+}
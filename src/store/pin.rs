@@ -0,0 +1,84 @@
+//! Methods of Store for managing pinned messages
+
+use super::{model::PinInfo, Store};
+use crate::core::Error;
+
+impl Store {
+    /// Pin the message in the room
+    ///
+    /// Returns `None` if the message has already been pinned.
+    pub async fn pin_message(
+        &self,
+        user_id: i64,
+        room_id: i64,
+        message_id: i64,
+    ) -> Result<Option<PinInfo>, Error> {
+        let pin = sqlx::query_as!(
+            PinInfo,
+            r#"
+                WITH insert_cte AS (
+                    INSERT INTO pins
+                        (room_id, message_id, pinner_id)
+                    VALUES
+                        ($1, $2, $3)
+                    ON CONFLICT DO NOTHING
+                    RETURNING
+                        message_id, pinner_id, pin_at
+                )
+                SELECT
+                    m.id AS message_id, m.seq, m.sender_id, u.nickname AS name,
+                    m.content, m.file_url, m.kind, p.pinner_id, p.pin_at
+                FROM insert_cte AS p
+                JOIN messages AS m ON m.id = p.message_id
+                JOIN users AS u ON u.id = m.sender_id
+            "#,
+            room_id,
+            message_id,
+            user_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(pin)
+    }
+
+    /// Unpin the message in the room and return whether it was pinned
+    pub async fn unpin_message(&self, room_id: i64, message_id: i64) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM pins
+                WHERE
+                    room_id = $1
+                    AND message_id = $2
+            "#,
+            room_id,
+            message_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get the pinned messages of the room with the latest pin first
+    pub async fn get_pins(&self, room_id: i64) -> Result<Vec<PinInfo>, Error> {
+        let pins = sqlx::query_as!(
+            PinInfo,
+            r#"
+                SELECT
+                    m.id AS message_id, m.seq, m.sender_id, u.nickname AS name,
+                    m.content, m.file_url, m.kind, p.pinner_id, p.pin_at
+                FROM pins AS p
+                JOIN messages AS m ON m.id = p.message_id
+                JOIN users AS u ON u.id = m.sender_id
+                WHERE p.room_id = $1
+                ORDER BY p.pin_at DESC
+            "#,
+            room_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pins)
+    }
+}
@@ -0,0 +1,218 @@
+//! Methods of Store for sharing the hub between nodes
+
+use super::Store;
+use crate::core::{
+    constant::{HUB_EVENT_CHANNEL, NODE_EXPIRE_SECONDS, TYPING_EXPIRE_SECONDS},
+    Error,
+};
+use redis::{
+    aio::{Connection, PubSub},
+    AsyncCommands,
+};
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+/// Lua function to sum the clients in a presence hash on the live nodes
+pub(super) const LIVE_CLIENTS_LUA: &str = r#"
+    local function live_clients(key)
+        local counts = redis.call('HGETALL', key)
+        local total = 0
+        for i = 1, #counts, 2 do
+            if redis.call('EXISTS', 'node:' .. counts[i]) == 1 then
+                total = total + tonumber(counts[i + 1])
+            end
+        end
+        return total
+    end
+"#;
+
+impl Store {
+    /// Publish the hub event to all nodes
+    pub async fn publish_hub_event(&self, event: &str) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let _: () = con.publish(HUB_EVENT_CHANNEL, event).await?;
+        Ok(())
+    }
+
+    /// Subscribe the hub events published by all nodes
+    pub async fn subscribe_hub_events(&self) -> Result<PubSub, Error> {
+        let con = self.client.get_async_connection().await?;
+        let mut pubsub = con.into_pubsub();
+        pubsub.subscribe(HUB_EVENT_CHANNEL).await?;
+        Ok(pubsub)
+    }
+
+    /// Mark the node alive, so that the clients on it are counted
+    ///
+    /// The mark expires unless it is refreshed, so the presence kept by a
+    /// node that crashed is ignored after a while.
+    pub async fn beat_node(&self, node: &str) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("node:{}", node);
+        let _: () = con.set_ex(key, 1, NODE_EXPIRE_SECONDS).await?;
+        Ok(())
+    }
+
+    /// Remove the node and the presence of its users
+    pub async fn remove_node(&self, node: &str, users: &[i64]) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+
+        let mut pipe = redis::pipe();
+        for user_id in users {
+            pipe.hdel(format!("presence:{}", user_id), node).ignore();
+        }
+        pipe.del(format!("node:{}", node)).ignore();
+        let _: () = pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// Set the number of clients of the user on the node
+    ///
+    /// Returns the number of clients of the user on all live nodes.
+    pub async fn set_presence(
+        &self,
+        user_id: i64,
+        node: &str,
+        clients: usize,
+    ) -> Result<usize, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("presence:{}", user_id);
+
+        let _: () = redis::pipe()
+            .atomic()
+            .set_ex(format!("node:{}", node), 1, NODE_EXPIRE_SECONDS)
+            .ignore()
+            .hset(key.as_str(), node, clients)
+            .ignore()
+            .query_async(&mut con)
+            .await?;
+
+        count_live_clients(&mut con, &key).await
+    }
+
+    /// Remove the user from the node
+    pub async fn remove_presence(&self, user_id: i64, node: &str) -> Result<(), Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("presence:{}", user_id);
+        let _: () = con.hdel(key, node).await?;
+        Ok(())
+    }
+
+    /// Get the number of clients of the user on all live nodes
+    pub async fn count_clients(&self, user_id: i64) -> Result<usize, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let key = format!("presence:{}", user_id);
+        count_live_clients(&mut con, &key).await
+    }
+
+    /// Record the user typing in the room, and return whether the user just
+    /// started typing on any node
+    pub async fn start_typing(&self, room_id: i64, user_id: i64) -> Result<bool, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+        let member = format!("{}:{}", room_id, user_id);
+        let added: usize = con.zadd("typing", member, now).await?;
+        Ok(added == 1)
+    }
+
+    /// Remove the user typing in the room, and return whether it was typing
+    pub async fn stop_typing(&self, room_id: i64, user_id: i64) -> Result<bool, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let member = format!("{}:{}", room_id, user_id);
+        let removed: usize = con.zrem("typing", member).await?;
+        Ok(removed == 1)
+    }
+
+    /// Remove the typing not refreshed in time on any node, and return the
+    /// room and user of them
+    ///
+    /// Every node sweeps, and each entry is only returned to one of them.
+    pub async fn sweep_typing(&self) -> Result<Vec<(i64, i64)>, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64;
+        let expire_at = now - TYPING_EXPIRE_SECONDS as i64 * 1000;
+
+        let sweep = redis::Script::new(
+            r#"
+                local expired = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+                if #expired > 0 then
+                    redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+                end
+                return expired
+            "#,
+        );
+        let members: Vec<String> = sweep
+            .key("typing")
+            .arg(expire_at)
+            .invoke_async(&mut con)
+            .await?;
+
+        let expired = members
+            .iter()
+            .filter_map(|m| {
+                let (room_id, user_id) = m.split_once(':')?;
+                Some((room_id.parse().ok()?, user_id.parse().ok()?))
+            })
+            .collect();
+        Ok(expired)
+    }
+
+    /// Get the users with a live client on any node
+    pub async fn online_users(&self, users: &[i64]) -> Result<Vec<i64>, Error> {
+        let mut con = self.client.get_async_connection().await?;
+        let script = redis::Script::new(&format!(
+            "{}{}",
+            LIVE_CLIENTS_LUA,
+            r#"
+                local online = {}
+                for i, key in ipairs(KEYS) do
+                    if live_clients(key) > 0 then
+                        table.insert(online, i)
+                    end
+                end
+                return online
+            "#
+        ));
+
+        let mut invocation = script.prepare_invoke();
+        for user_id in users {
+            invocation.key(format!("presence:{}", user_id));
+        }
+        let online: Vec<usize> = invocation.invoke_async(&mut con).await?;
+
+        // the script returns the 1-based positions of the online users
+        Ok(online.into_iter().map(|i| users[i - 1]).collect())
+    }
+}
+
+/// Sum the clients in the presence hash, and drop the fields of dead nodes
+async fn count_live_clients(con: &mut Connection, key: &str) -> Result<usize, Error> {
+    let counts: HashMap<String, usize> = con.hgetall(key).await?;
+    if counts.is_empty() {
+        return Ok(0);
+    }
+
+    let nodes: Vec<&String> = counts.keys().collect();
+    let alive: Vec<Option<u8>> = redis::cmd("MGET")
+        .arg(
+            nodes
+                .iter()
+                .map(|n| format!("node:{}", n))
+                .collect::<Vec<_>>(),
+        )
+        .query_async(con)
+        .await?;
+
+    let mut total = 0;
+    let mut dead = Vec::new();
+    for (node, alive) in nodes.into_iter().zip(alive) {
+        match alive {
+            Some(_) => total += counts[node],
+            None => dead.push(node),
+        }
+    }
+    if !dead.is_empty() {
+        let _: () = con.hdel(key, dead).await?;
+    }
+    Ok(total)
+}
@@ -0,0 +1,161 @@
+//! Methods of Store for managing message reactions
+
+use super::{
+    model::{MessageInfo, ReactionInfo},
+    Store,
+};
+use crate::{
+    api::{ReactionRequest, ReactionResponse},
+    core::Error,
+};
+use std::collections::HashMap;
+
+impl Store {
+    /// Add a reaction of the user to the message
+    ///
+    /// Returns the response and whether the reaction is newly added.
+    pub async fn add_reaction(
+        &self,
+        user_id: i64,
+        room_id: i64,
+        req: &ReactionRequest,
+    ) -> Result<(ReactionResponse, bool), Error> {
+        let result = sqlx::query!(
+            r#"
+                INSERT INTO reactions
+                    (message_id, user_id, emoji)
+                VALUES
+                    ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+            "#,
+            req.message_id,
+            user_id,
+            req.emoji,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let rsp = self.reaction_response(user_id, room_id, req).await?;
+        Ok((rsp, result.rows_affected() > 0))
+    }
+
+    /// Remove a reaction of the user from the message
+    ///
+    /// Returns the response and whether the reaction existed.
+    pub async fn remove_reaction(
+        &self,
+        user_id: i64,
+        room_id: i64,
+        req: &ReactionRequest,
+    ) -> Result<(ReactionResponse, bool), Error> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM reactions
+                WHERE
+                    message_id = $1
+                    AND user_id = $2
+                    AND emoji = $3
+            "#,
+            req.message_id,
+            user_id,
+            req.emoji,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let rsp = self.reaction_response(user_id, room_id, req).await?;
+        Ok((rsp, result.rows_affected() > 0))
+    }
+
+    /// Attach the aggregated reactions to the messages from the view of the user
+    pub async fn fill_reactions(
+        &self,
+        user_id: i64,
+        messages: &mut [MessageInfo],
+    ) -> Result<(), Error> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+
+        let rows = sqlx::query_as!(
+            ReactionRow,
+            r#"
+                SELECT
+                    message_id, emoji, count(*) AS count,
+                    bool_or(user_id = $2) AS reacted
+                FROM reactions
+                WHERE message_id = ANY($1)
+                GROUP BY message_id, emoji
+                ORDER BY message_id, min(create_at)
+            "#,
+            &ids,
+            user_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut reactions: HashMap<i64, Vec<ReactionInfo>> = HashMap::new();
+        for row in rows {
+            reactions
+                .entry(row.message_id)
+                .or_default()
+                .push(row.into());
+        }
+
+        for message in messages.iter_mut() {
+            message.reactions = reactions.remove(&message.id).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// Count the users reacting with the emoji on the message
+    async fn reaction_response(
+        &self,
+        user_id: i64,
+        room_id: i64,
+        req: &ReactionRequest,
+    ) -> Result<ReactionResponse, Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+                SELECT count(*)
+                FROM reactions
+                WHERE
+                    message_id = $1
+                    AND emoji = $2
+            "#,
+            req.message_id,
+            req.emoji,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let rsp = ReactionResponse {
+            room_id,
+            message_id: req.message_id,
+            user_id,
+            emoji: req.emoji.clone(),
+            count: count.unwrap_or(0),
+        };
+        Ok(rsp)
+    }
+}
+
+// ========================// Conversions //======================== //
+
+struct ReactionRow {
+    message_id: i64,
+    emoji: String,
+    count: Option<i64>,
+    reacted: Option<bool>,
+}
+
+impl From<ReactionRow> for ReactionInfo {
+    fn from(v: ReactionRow) -> Self {
+        Self {
+            emoji: v.emoji,
+            count: v.count.unwrap_or(0),
+            reacted: v.reacted.unwrap_or(false),
+        }
+    }
+}
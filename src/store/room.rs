@@ -105,7 +105,10 @@ impl Store {
         .await?;
 
         members.sort_by(cmp_member);
-        Ok((room, members).into())
+        let mut room: RoomInfo = (room, members).into();
+        room.pins = self.get_pins(room_id).await?;
+
+        Ok(room)
     }
 
     /// Delete the room and return the member's id
@@ -143,7 +146,8 @@ impl Store {
         // delete messages stored in redis
         let mut con = self.client.get_async_connection().await?;
         let key = format!("room:{}", room_id);
-        con.del(key).await?;
+        let version_key = format!("room:{}:version", room_id);
+        let _: () = con.del(&[key, version_key]).await?;
 
         Ok(members_id)
     }
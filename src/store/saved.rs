@@ -0,0 +1,148 @@
+//! Methods of Store for managing saved messages
+
+use super::{model::SavedMessageInfo, Store};
+use crate::{
+    api::{ListSavedRequest, ListSavedResponse, SaveMessageResponse},
+    core::{constant::CATEGORY_PRIVATE, Error},
+};
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+impl Store {
+    /// Save the message for the user
+    ///
+    /// Returns `None` if the message has already been saved.
+    pub async fn add_saved(
+        &self,
+        user_id: i64,
+        message_id: i64,
+    ) -> Result<Option<SaveMessageResponse>, Error> {
+        let rsp = sqlx::query_as!(
+            SaveMessageResponse,
+            r#"
+                WITH insert_cte AS (
+                    INSERT INTO saved_messages
+                        (user_id, message_id)
+                    VALUES
+                        ($1, $2)
+                    ON CONFLICT DO NOTHING
+                    RETURNING
+                        id, message_id, save_at
+                )
+                SELECT
+                    s.id, s.message_id, m.room_id, s.save_at
+                FROM insert_cte AS s
+                JOIN messages AS m ON m.id = s.message_id
+            "#,
+            user_id,
+            message_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(rsp)
+    }
+
+    /// Remove the message from the saved list and return whether it was saved
+    pub async fn remove_saved(&self, user_id: i64, message_id: i64) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+                DELETE FROM saved_messages
+                WHERE
+                    user_id = $1
+                    AND message_id = $2
+            "#,
+            user_id,
+            message_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get a page of saved messages with the latest saved first
+    ///
+    /// Messages in the rooms that the user has left are not listed.
+    pub async fn list_saved(
+        &self,
+        user_id: i64,
+        req: &ListSavedRequest,
+    ) -> Result<ListSavedResponse, Error> {
+        // fetch one more row to know whether there are more messages
+        let limit = req.page_size + 1;
+
+        let mut rows = sqlx::query_as!(
+            SavedRow,
+            r#"
+                SELECT
+                    s.id, s.message_id, m.room_id, s.save_at, r.category AS room_category,
+                    CASE WHEN r.category = $3 THEN o.nickname ELSE r.name END AS room_name
+                FROM saved_messages AS s
+                JOIN messages AS m ON m.id = s.message_id
+                JOIN members AS y ON y.room_id = m.room_id AND y.member_id = s.user_id
+                JOIN rooms AS r ON r.id = m.room_id
+                LEFT JOIN LATERAL (
+                    SELECT u.nickname
+                    FROM members AS x
+                    JOIN users AS u ON u.id = x.member_id
+                    WHERE
+                        x.room_id = r.id
+                        AND x.member_id <> s.user_id
+                    LIMIT 1
+                ) AS o ON true
+                WHERE
+                    s.user_id = $1
+                    AND ($2::bigint IS NULL OR s.id < $2)
+                ORDER BY s.id DESC
+                LIMIT $4
+            "#,
+            user_id,
+            req.before,
+            CATEGORY_PRIVATE,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let has_more = rows.len() as i64 > req.page_size;
+        rows.truncate(req.page_size as usize);
+
+        // get the messages and put them back in the saved order
+        let ids: Vec<i64> = rows.iter().map(|x| x.message_id).collect();
+        let mut messages: HashMap<i64, _> = self
+            .get_messages_by_ids(user_id, &ids)
+            .await?
+            .into_iter()
+            .map(|m| (m.id, m))
+            .collect();
+
+        let mut saved = Vec::new();
+        for row in rows {
+            if let Some(message) = messages.remove(&row.message_id) {
+                saved.push(SavedMessageInfo {
+                    id: row.id,
+                    room_id: row.room_id,
+                    room_name: row.room_name.unwrap_or_default(),
+                    room_category: row.room_category,
+                    save_at: row.save_at,
+                    message,
+                });
+            }
+        }
+
+        let rsp = ListSavedResponse { saved, has_more };
+        Ok(rsp)
+    }
+}
+
+// ========================// Conversions //======================== //
+
+struct SavedRow {
+    id: i64,
+    message_id: i64,
+    room_id: i64,
+    save_at: OffsetDateTime,
+    room_category: String,
+    room_name: Option<String>,
+}
@@ -13,7 +13,7 @@ impl Store {
         seconds: usize,
     ) -> Result<(), Error> {
         let mut con = self.client.get_async_connection().await?;
-        con.set_ex(id.to_string(), refresh_token, seconds).await?;
+        let _: () = con.set_ex(id.to_string(), refresh_token, seconds).await?;
         Ok(())
     }
 
@@ -25,7 +25,7 @@ impl Store {
 
     pub async fn delete_session(&self, id: Uuid) -> Result<(), Error> {
         let mut con = self.client.get_async_connection().await?;
-        con.del(id.to_string()).await?;
+        let _: () = con.del(id.to_string()).await?;
         Ok(())
     }
 }
@@ -81,7 +81,7 @@ impl Store {
         let user_str = serde_json::to_string(&user)?;
         let mut con = self.client.get_async_connection().await?;
         let key = format!("user:{}", user.id);
-        con.set_ex(key, user_str, 86400).await?;
+        let _: () = con.set_ex(key, user_str, 86400).await?;
         Ok(())
     }
 
@@ -116,6 +116,26 @@ impl Store {
         Ok(user)
     }
 
+    pub async fn update_last_seen(
+        &self,
+        user_id: i64,
+        last_seen: OffsetDateTime,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+                UPDATE users
+                SET last_seen = $1
+                WHERE id = $2
+            "#,
+            last_seen,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn find_user(&self, username: &str) -> Result<Option<User>, Error> {
         let user = sqlx::query_as!(
             User,
@@ -155,7 +175,7 @@ impl Store {
         .fetch_all(&self.pool)
         .await?;
 
-        let total = match result.get(0) {
+        let total = match result.first() {
             Some(row) => row.total.unwrap_or(0),
             None => 0,
         };
@@ -168,7 +188,7 @@ impl Store {
         let hashed_password: Option<String> = arg
             .password
             .as_ref()
-            .map(|v| hash_password(&v))
+            .map(|v| hash_password(v))
             .transpose()?;
 
         let user = sqlx::query_as!(
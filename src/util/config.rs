@@ -1,3 +1,4 @@
+use crate::conn::SlowClientPolicy;
 use std::env;
 
 #[derive(Debug)]
@@ -10,6 +11,13 @@ pub struct Config {
     pub access_token_minutes: i64,
     pub refresh_token_days: i64,
     pub session_seconds: usize,
+    pub message_edit_minutes: i64,
+    pub resume_grace_seconds: u64,
+    pub hub_fanout: bool,
+    pub slow_client_policy: SlowClientPolicy,
+    pub reconnect_seconds: u64,
+    pub ws_compression: bool,
+    pub ws_compression_min_size: usize,
 }
 
 impl Config {
@@ -34,6 +42,41 @@ impl Config {
             .try_into()
             .expect("Conversion error of session days");
 
+        let message_edit_minutes: i64 = env::var("MESSAGE_EDIT_MINUTES")
+            .unwrap_or("15".to_owned())
+            .parse()
+            .expect("MESSAGE_EDIT_MINUTES must be a number");
+
+        let resume_grace_seconds: u64 = env::var("RESUME_GRACE_SECONDS")
+            .unwrap_or("60".to_owned())
+            .parse()
+            .expect("RESUME_GRACE_SECONDS must be a number");
+
+        let hub_fanout: bool = env::var("HUB_FANOUT")
+            .unwrap_or("false".to_owned())
+            .parse()
+            .expect("HUB_FANOUT must be true or false");
+
+        let slow_client_policy: SlowClientPolicy = env::var("SLOW_CLIENT_POLICY")
+            .unwrap_or("drop-oldest".to_owned())
+            .parse()
+            .expect("SLOW_CLIENT_POLICY must be drop-oldest, disconnect or resync");
+
+        let reconnect_seconds: u64 = env::var("RECONNECT_SECONDS")
+            .unwrap_or("5".to_owned())
+            .parse()
+            .expect("RECONNECT_SECONDS must be a number");
+
+        let ws_compression: bool = env::var("WS_COMPRESSION")
+            .unwrap_or("false".to_owned())
+            .parse()
+            .expect("WS_COMPRESSION must be true or false");
+
+        let ws_compression_min_size: usize = env::var("WS_COMPRESSION_MIN_SIZE")
+            .unwrap_or("1024".to_owned())
+            .parse()
+            .expect("WS_COMPRESSION_MIN_SIZE must be a number");
+
         Config {
             server_addr,
             database_url,
@@ -43,6 +86,13 @@ impl Config {
             access_token_minutes,
             refresh_token_days,
             session_seconds,
+            message_edit_minutes,
+            resume_grace_seconds,
+            hub_fanout,
+            slow_client_policy,
+            reconnect_seconds,
+            ws_compression,
+            ws_compression_min_size,
         }
     }
 }
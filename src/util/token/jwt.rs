@@ -95,12 +95,13 @@ impl JwtToken {
     }
 
     pub fn verify(&self, token: &str) -> Result<Claims, Error> {
-        let data = decode::<Claims>(&token, &self.decoding, &Validation::default()).map_err(
-            |e| match e.into_kind() {
+        let validation = Validation::default();
+        let data = decode::<Claims>(token, &self.decoding, &validation).map_err(|e| {
+            match e.into_kind() {
                 ErrorKind::ExpiredSignature => Error::ExpiredToken,
                 _ => Error::Unauthorized,
-            },
-        )?;
+            }
+        })?;
 
         Ok(data.claims)
     }